@@ -1,98 +1,460 @@
 use hidapi;
 use signal_hook::consts::signal;
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::{
+    fs, mem,
+    path::PathBuf,
     sync::{
-        atomic::AtomicBool,
+        atomic::{AtomicBool, Ordering},
         Arc, Mutex,
     },
-    thread,
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
 };
 
+use crate::binding::{Action, Binding, Chord, MacrosBinding};
 use crate::config;
 use crate::config::Config;
-use crate::device::Device;
-use crate::macros::{self, shortcut::ShortCut};
+use crate::device::{Device, SharedLayers};
+use crate::error::Error;
+use crate::executor::{Executor, MacroRunner};
+use crate::format::Format;
+use crate::hotplug::Hotplug;
+use crate::input::{self, DeviceSelector, InputBackend, InputError};
+use crate::layer::LayerAction;
+use crate::macros::{
+    passthrough::Passthrough,
+    sequence::{Sequence, Step as SequenceStep},
+    shortcut::ShortCut,
+    text::TypeText,
+    Macro,
+};
+use crate::output::{self, MouseEvent, OutputBackend};
+use crate::stop::StopFlag;
+
+// How often the configuration file is looked at for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
 
 pub struct App {
+    config_path: String,
+    format: Format,
+    // Files of the running configuration, included ones as well
+    files: Vec<PathBuf>,
+    output_config: config::Output,
+    output: Arc<dyn OutputBackend>,
     hid_api: Arc<Mutex<hidapi::HidApi>>,
-    devices: Vec<Device>,
+    executor: Arc<Executor>,
+    hotplug: Arc<Hotplug>,
+    devices: Vec<RunningDevice>,
+}
+
+// Everything of a configured device besides its macros. Device keeps its open
+// handle on reload while they stay the same.
+#[derive(PartialEq)]
+struct DeviceSetup {
+    selector: DeviceSelector,
+    input: config::Input,
+    report_ids: config::ReportIds,
+    report_format: config::ReportFormat,
+    reconnect: config::Reconnect,
+}
+
+// Device built from configuration, not listening yet
+struct PreparedDevice {
+    setup: DeviceSetup,
+    input: Box<dyn InputBackend>,
+    layers: Vec<MacrosBinding>,
+}
+
+struct RunningDevice {
+    setup: DeviceSetup,
+    layers: SharedLayers,
+    stop: StopFlag,
+    thread: JoinHandle<Result<(), Error>>,
 }
 
 impl App {
-    pub fn new(configuration: Config) -> Result<App, ()> {
+    pub fn new(config_path: &str, format: Format) -> Result<App, Error> {
+        let configuration = config::load(config_path, format)?;
+        let output = output::backend(configuration.output())?;
+        let hid_api = hidapi::HidApi::new().map_err(|e| Error::Hid(e.to_string()))?;
+
         let mut app = App {
-            hid_api: Arc::new(Mutex::new(hidapi::HidApi::new().unwrap())),
+            config_path: config_path.to_string(),
+            format,
+            files: configuration.files.clone(),
+            output_config: configuration.output(),
+            output: output.clone(),
+            hid_api: Arc::new(Mutex::new(hid_api)),
+            executor: Arc::new(Executor::new(output)),
+            hotplug: Hotplug::start(),
             devices: Vec::new(),
         };
 
-        app.init(configuration);
+        for device in app.prepare(configuration)? {
+            app.start(device);
+        }
 
         Ok(app)
     }
 
-    pub fn run(&mut self) -> Result<(), ()> {
-        let mut threads = Vec::new();
+    // Listens to devices until stopped, error of a device which was given up is returned.
+    // Configuration is reloaded when any of its files changes or on SIGHUP, also while
+    // it has no devices. Application ends once all devices were given up.
+    pub fn run(&mut self) -> Result<(), Error> {
         let stop = Arc::new(AtomicBool::new(false));
+        let reload = Arc::new(AtomicBool::new(false));
 
-        // Run devices in separate threads
-        while self.devices.len() > 0 {
-            let mut device = self.devices.pop().unwrap();
-            let stop = stop.clone();
-            threads.push(thread::spawn(move || {
-                device.listen(stop);
-            }));
-        }
+        let register = |signal, flag: &Arc<AtomicBool>| {
+            signal_hook::flag::register(signal, flag.clone())
+                .map_err(|e| Error::Signal(e.to_string()))
+        };
+        register(signal::SIGTERM, &stop)?;
+        register(signal::SIGINT, &stop)?;
+        #[cfg(unix)]
+        register(signal::SIGHUP, &reload)?;
+
+        let mut result = Ok(());
+        let mut seen = modified(&self.files);
+
+        while !stop.load(Ordering::Relaxed) && (result.is_ok() || !self.devices.is_empty()) {
+            thread::sleep(WATCH_INTERVAL);
+            self.collect_finished(&mut result);
 
-        signal_hook::flag::register(signal::SIGTERM, stop.clone()).unwrap();
-        signal_hook::flag::register(signal::SIGINT, stop.clone()).unwrap();
+            if modified(&self.files) != seen || reload.swap(false, Ordering::Relaxed) {
+                self.reload();
+                seen = modified(&self.files);
+            }
+        }
 
-        for thread in threads.into_iter() {
-            thread.join().unwrap();
+        self.stop_devices(&self.devices);
+        for device in self.devices.drain(..) {
+            record(&mut result, device.join());
         }
+        // Stop macros still running, so they do not leave keys pressed
+        self.executor.shutdown();
         println!("Closing application");
 
-        Ok(())
+        result
+    }
+
+    // Applies the configuration files, the running configuration stays active when they are invalid
+    fn reload(&mut self) {
+        println!("Reloading configuration {}", self.config_path);
+
+        let prepared = config::load(&self.config_path, self.format).and_then(|configuration| {
+            if configuration.output() != self.output_config {
+                return Err(Error::Config(
+                    "output cannot be changed while running, restart to apply it".to_string(),
+                ));
+            }
+            let files = configuration.files.clone();
+            Ok((files, self.prepare(configuration)?))
+        });
+
+        match prepared {
+            Ok((files, devices)) => {
+                self.files = files;
+                self.apply(devices);
+            }
+            Err(e) => println!("Configuration not reloaded: {}", e),
+        }
     }
 
-    fn init(&mut self, config: Config) {
+    // Swaps bindings of unchanged devices, closes removed devices and starts new ones
+    fn apply(&mut self, devices: Vec<PreparedDevice>) {
+        let mut removed = mem::take(&mut self.devices);
+        let mut added = Vec::new();
+
+        for device in devices {
+            match removed.iter().position(|r| r.setup == device.setup) {
+                Some(i) => {
+                    let running = removed.remove(i);
+                    running.layers.replace(device.layers);
+                    self.devices.push(running);
+                }
+                None => added.push(device),
+            }
+        }
+
+        // Closed before new devices start, changed device may be opened again
+        for device in &removed {
+            println!("Closing {}", device.setup.selector);
+        }
+        self.stop_devices(&removed);
+        for device in removed {
+            if let Err(e) = device.join() {
+                println!("{}", e);
+            }
+        }
+
+        for device in added {
+            println!("Starting {}", device.setup.selector);
+            self.start(device);
+        }
+    }
+
+    // Takes out devices which were given up, first error decides the exit code
+    fn collect_finished(&mut self, result: &mut Result<(), Error>) {
+        let (finished, running): (Vec<_>, Vec<_>) = mem::take(&mut self.devices)
+            .into_iter()
+            .partition(|d| d.thread.is_finished());
+        self.devices = running;
+
+        for device in finished {
+            record(result, device.join());
+        }
+    }
+
+    // Devices waiting for hotplug events are woken up to notice the stop
+    fn stop_devices(&self, devices: &[RunningDevice]) {
+        for device in devices {
+            device.stop.stop();
+        }
+        self.hotplug.wake();
+    }
+
+    fn start(&mut self, prepared: PreparedDevice) {
+        let mut device = Device::new(
+            prepared.setup.selector.clone(),
+            prepared.input,
+            self.hotplug.clone(),
+            prepared.setup.reconnect,
+            prepared.layers,
+        );
+        let layers = device.layers();
+        let stop = StopFlag::new();
+        let device_stop = stop.clone();
+
+        self.devices.push(RunningDevice {
+            setup: prepared.setup,
+            layers,
+            stop,
+            thread: thread::spawn(move || device.listen(device_stop)),
+        });
+    }
+
+    // Builds all devices, so nothing is applied when any of them fails
+    fn prepare(&self, config: Config) -> Result<Vec<PreparedDevice>, Error> {
+        let (executor, output) = (&self.executor, &self.output);
+        let mut devices = Vec::new();
+
         for conf_device in config.devices {
-            let mut macros = HashMap::new();
-            for conf_macro in conf_device.macros {
-                let macr: Box<dyn macros::Macro + Send> = match conf_macro.oneof_macro {
-                    config::OneOfMacros::Shell(shell_macro) => Box::new(shell_macro),
-                    config::OneOfMacros::Shortcut(shortcut_config) => {
-                        Box::new(ShortCut::new(shortcut_config.keys))
+            let input = input::backend(&conf_device, &self.hid_api)?;
+            let setup = DeviceSetup {
+                selector: DeviceSelector::new(&conf_device),
+                input: conf_device.input(),
+                report_ids: conf_device.report_ids(),
+                report_format: conf_device.report_format(),
+                reconnect: conf_device.reconnect(),
+            };
+            let selector = &setup.selector;
+
+            let mut layer_names = vec![config::BASE_LAYER_NAME.to_string()];
+            layer_names.extend(conf_device.layers.iter().map(|l| l.name.clone()));
+
+            let base = bindings(conf_device.macros, &layer_names, executor, output)
+                .map_err(|e| Error::Config(format!("{}: {}", selector, e)))?;
+            let mut layers = vec![base];
+            for conf_layer in conf_device.layers {
+                let layer =
+                    bindings(conf_layer.macros, &layer_names, executor, output).map_err(|e| {
+                        Error::Config(format!("{}, layer {}: {}", selector, conf_layer.name, e))
+                    })?;
+                layers.push(layer);
+            }
+
+            devices.push(PreparedDevice {
+                setup,
+                input,
+                layers,
+            });
+        }
+
+        Ok(devices)
+    }
+}
+
+impl RunningDevice {
+    // Waits for the device thread, its panic is reported as an error of the device
+    fn join(self) -> Result<(), Error> {
+        self.thread.join().unwrap_or_else(|panic| {
+            let message = match panic.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => panic.downcast_ref::<String>().cloned().unwrap_or_default(),
+            };
+            Err(Error::Device {
+                device: self.setup.selector.to_string(),
+                source: InputError::Other(format!("device thread panicked: {}", message)),
+            })
+        })
+    }
+}
+
+// First error is returned, others are only reported
+fn record(result: &mut Result<(), Error>, device_result: Result<(), Error>) {
+    if let Err(e) = device_result {
+        if result.is_ok() {
+            *result = Err(e);
+        } else {
+            println!("{}", e);
+        }
+    }
+}
+
+// Modification times of the files, None for a file which cannot be read
+fn modified(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+fn bindings(
+    conf_macros: Vec<config::Macro>,
+    layer_names: &[String],
+    executor: &Arc<Executor>,
+    output: &Arc<dyn OutputBackend>,
+) -> Result<MacrosBinding, String> {
+    let mut macros = HashMap::new();
+
+    for conf_macro in conf_macros {
+        let trigger = conf_macro.trigger();
+        let overlap = conf_macro.overlap;
+        let runner = |action: Box<dyn Macro + Send>| {
+            Action::Macro(MacroRunner::new(action, overlap, executor))
+        };
+        let action = match conf_macro.oneof_macro {
+            config::OneOfMacros::Shell(shell_macro) => runner(Box::new(shell_macro)),
+            config::OneOfMacros::Shortcut(shortcut_config) => {
+                runner(Box::new(ShortCut::new(shortcut_config.keys, output.clone())))
+            }
+            config::OneOfMacros::Passthrough(passthrough_config) => runner(Box::new(
+                Passthrough::new(passthrough_config.keys, output.clone()),
+            )),
+            config::OneOfMacros::Type(text_config) => {
+                let delay = text_config.delay();
+                runner(Box::new(TypeText::new(text_config.text, delay, output.clone())))
+            }
+            config::OneOfMacros::Sequence(sequence_config) => {
+                let steps = sequence_config
+                    .steps
+                    .into_iter()
+                    .map(|s| step(s, output))
+                    .collect();
+                runner(Box::new(Sequence::new(steps, output.clone())))
+            }
+            config::OneOfMacros::Layer(layer_config) => {
+                let layer = match layer_names.iter().position(|n| *n == layer_config.name) {
+                    Some(l) => l,
+                    None => {
+                        return Err(format!(
+                            "macro on key {:?} switches to unknown layer {}",
+                            conf_macro.key, layer_config.name
+                        ))
                     }
                 };
-                macros.insert(conf_macro.key.clone(), macr);
+                Action::Layer(LayerAction {
+                    layer,
+                    mode: layer_config.mode,
+                })
             }
+            config::OneOfMacros::Panic(_) => Action::Panic(executor.clone()),
+            // Replaced by definitions when the configuration is loaded
+            config::OneOfMacros::Named(name) => return Err(format!("unknown macro {}", name)),
+        };
+        let chord = Chord {
+            modifiers: conf_macro.modifiers.into_iter().collect::<BTreeSet<_>>(),
+            key: conf_macro.key,
+        };
+        macros.entry(chord).or_insert_with(Vec::new).push(Binding {
+            matching: conf_macro.modifiers_match,
+            trigger,
+            action,
+        });
+    }
+
+    Ok(macros)
+}
 
-            self.devices.push(Device::new(
-                conf_device.vid,
-                conf_device.pid,
-                macros,
-                self.hid_api.clone(),
-            ));
+fn step(conf_step: config::Step, output: &Arc<dyn OutputBackend>) -> SequenceStep {
+    match conf_step.oneof_step {
+        config::OneOfSteps::Shell(shell_macro) => SequenceStep::Macro(Box::new(shell_macro)),
+        config::OneOfSteps::Shortcut(shortcut_config) => SequenceStep::Macro(Box::new(
+            ShortCut::new(shortcut_config.keys, output.clone()),
+        )),
+        config::OneOfSteps::Type(text_config) => {
+            let delay = text_config.delay();
+            SequenceStep::Macro(Box::new(TypeText::new(
+                text_config.text,
+                delay,
+                output.clone(),
+            )))
+        }
+        config::OneOfSteps::KeyDown(key) => SequenceStep::KeyDown(key),
+        config::OneOfSteps::KeyUp(key) => SequenceStep::KeyUp(key),
+        config::OneOfSteps::ButtonDown(button) => {
+            SequenceStep::Mouse(MouseEvent::ButtonDown(button))
         }
+        config::OneOfSteps::ButtonUp(button) => SequenceStep::Mouse(MouseEvent::ButtonUp(button)),
+        config::OneOfSteps::Scroll(scroll) => SequenceStep::Mouse(MouseEvent::Scroll {
+            x: scroll.x,
+            y: scroll.y,
+        }),
+        config::OneOfSteps::MoveTo(position) => SequenceStep::Mouse(MouseEvent::MoveTo {
+            x: position.x,
+            y: position.y,
+        }),
+        config::OneOfSteps::Wait(duration) => SequenceStep::Wait(duration),
     }
 }
 
-pub fn list_devices() {
-    let hid_api = hidapi::HidApi::new().unwrap();
+pub fn list_devices() -> Result<(), Error> {
+    let hid_api = hidapi::HidApi::new().map_err(|e| Error::Hid(e.to_string()))?;
 
     println!("Found HID USB devices:\n");
 
     for device in hid_api.device_list() {
-        
         println!(
-            "SN:{:#?} {}\tVID: {}\t PID: {}\t\tNAME: {}",
+            "SN:{:#?}\tVID: {}\t PID: {}\t\tNAME: {}",
             device.serial_number().unwrap_or("N/A"),
-            device.usage(),            
             device.vendor_id(),
             device.product_id(),
             device.product_string().unwrap_or("N/A")
         );
+        println!(
+            "\tINTERFACE: {}\tUSAGE PAGE: {:#06x}\tUSAGE: {:#06x}\tPATH: {}",
+            device.interface_number(),
+            device.usage_page(),
+            device.usage(),
+            device.path().to_string_lossy()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        use crate::input::{event_device::EvdevBackend, InputBackend};
+
+        println!("\nFound input event devices (input: evdev):\n");
+
+        for device in EvdevBackend.enumerate() {
+            println!(
+                "SN:{:#?}\tVID: {}\t PID: {}\t\tNAME: {}",
+                device.serial.as_deref().unwrap_or("N/A"),
+                device.vid,
+                device.pid,
+                device.name
+            );
+            println!(
+                "\tINTERFACE: {}\tPATH: {}",
+                device
+                    .interface_number
+                    .map_or("N/A".to_string(), |i| i.to_string()),
+                device.path
+            );
+        }
     }
+
+    Ok(())
 }
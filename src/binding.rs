@@ -0,0 +1,70 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::ModifiersMatch;
+use crate::executor::{Executor, MacroRunner};
+use crate::keyboard::Key;
+use crate::layer::LayerAction;
+
+// Key together with the modifiers which have to be held while it is used
+#[derive(PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub modifiers: BTreeSet<Key>,
+    pub key: Key,
+}
+
+// Gesture of the key which runs the macro
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Trigger {
+    Press,
+    Release,
+    // Key held at least for the threshold
+    Hold(Duration),
+    // Second press within the window after the first release
+    DoubleTap(Duration),
+}
+
+pub enum Action {
+    Macro(MacroRunner),
+    Layer(LayerAction),
+    // Cancels all running macros
+    Panic(Arc<Executor>),
+}
+
+pub struct Binding {
+    pub matching: ModifiersMatch,
+    pub trigger: Trigger,
+    pub action: Action,
+}
+
+// Several bindings can share the chord when they differ in trigger
+pub type MacrosBinding = HashMap<Chord, Vec<Binding>>;
+
+// Finds bindings for the key with currently held modifiers.
+// Exact chord wins, otherwise the most specific subset chord is used.
+pub fn find<'a>(bindings: &'a MacrosBinding, key: &Key, held: &BTreeSet<Key>) -> Vec<&'a Binding> {
+    let chord = Chord {
+        modifiers: held.clone(),
+        key: key.clone(),
+    };
+
+    if let Some(found) = bindings.get(&chord) {
+        return found.iter().collect();
+    }
+
+    bindings
+        .iter()
+        .filter(|(chord, _)| chord.key == *key && chord.modifiers.is_subset(held))
+        .map(|(chord, found)| {
+            let subset: Vec<&Binding> = found
+                .iter()
+                .filter(|b| b.matching == ModifiersMatch::Subset)
+                .collect();
+            (chord.modifiers.len(), subset)
+        })
+        .filter(|(_, subset)| !subset.is_empty())
+        .max_by_key(|(specificity, _)| *specificity)
+        .map(|(_, subset)| subset)
+        .unwrap_or_default()
+}
@@ -0,0 +1,742 @@
+// Validation of configuration files. All problems are collected at once,
+// each one with the line and column of the value it is about.
+use std::collections::{BTreeSet, HashSet};
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde_yaml::Value;
+
+use crate::binding::{Chord, Trigger};
+use crate::config::{
+    Config, DeviceConfiguration, Macro, NamedMacro, OneOfMacros, OneOfSteps, Output,
+    BASE_LAYER_NAME,
+};
+use crate::format::Format;
+use crate::input::DeviceSelector;
+use crate::keyboard::Key;
+use crate::macros::shell::ShellMacro;
+use crate::output;
+
+// Step of the path from the document root to a value
+#[derive(Clone, Debug)]
+pub enum Segment {
+    Key(&'static str),
+    // Key chosen in the file, e.g. name of a macro
+    Name(String),
+    Index(usize),
+}
+
+impl Segment {
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            Segment::Key(key) => Some(key),
+            Segment::Name(name) => Some(name),
+            Segment::Index(_) => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Problem {
+    // Line and column, 1-based
+    pub position: Option<(usize, usize)>,
+    pub path: String,
+    pub message: String,
+}
+
+impl Problem {
+    // Problem of the value at the path
+    pub fn at(source: &str, format: Format, path: &[Segment], message: String) -> Problem {
+        Problem {
+            position: format.locate(source, path),
+            path: path_to_string(path),
+            message,
+        }
+    }
+}
+
+impl Problem {
+    // Prefixed by the file, e.g. config.yaml:3:5: devices[0]: ...
+    pub fn in_file(&self, file: &Path) -> String {
+        match self.position {
+            Some(_) => format!("{}:{}", file.display(), self),
+            None => format!("{}: {}", file.display(), self),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((line, column)) = self.position {
+            write!(f, "{}:{}: ", line, column)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+// What all files of the configuration define. Files are checked one by one,
+// but may use named macros and layers of the other ones.
+#[derive(Default)]
+pub struct Context {
+    output: Output,
+    macros: HashSet<String>,
+    // Layer names of devices, entries with the same selectors are one device
+    layers: Vec<(DeviceSelector, String)>,
+}
+
+impl Context {
+    // Roots of all files, later files override output of earlier ones
+    pub fn new<'a>(roots: impl IntoIterator<Item = &'a Value>) -> Context {
+        let mut context = Context::default();
+
+        for root in roots {
+            if let Some(output) = root.get("output") {
+                if let Ok(output) = serde_yaml::from_value(output.clone()) {
+                    context.output = output;
+                }
+            }
+
+            let macros = root.get("macros").and_then(Value::as_mapping);
+            let names = macros.into_iter().flatten().filter_map(|(k, _)| k.as_str());
+            context.macros.extend(names.map(str::to_string));
+
+            let devices = root.get("devices").and_then(Value::as_sequence);
+            for device in devices.into_iter().flatten() {
+                let selector = match serde_yaml::from_value::<DeviceSelector>(device.clone()) {
+                    Ok(selector) => selector,
+                    Err(_) => continue,
+                };
+                let layers = device.get("layers").and_then(Value::as_sequence);
+                for layer in layers.into_iter().flatten() {
+                    if let Some(name) = layer.get("name").and_then(Value::as_str) {
+                        context.layers.push((selector.clone(), name.to_string()));
+                    }
+                }
+            }
+        }
+
+        context
+    }
+
+    fn layer_names(&self, selector: Option<&DeviceSelector>) -> Vec<String> {
+        let mut names = vec![BASE_LAYER_NAME.to_string()];
+        let layers = self.layers.iter();
+        let layers = layers.filter(|(s, _)| selector.is_none_or(|selector| s == selector));
+        names.extend(layers.map(|(_, name)| name.clone()));
+        names
+    }
+}
+
+struct Checker<'a> {
+    source: &'a str,
+    format: Format,
+    context: &'a Context,
+    problems: Vec<Problem>,
+}
+
+// Parses and validates one configuration file
+pub fn validate(source: &str, format: Format, context: &Context) -> Result<Config, Vec<Problem>> {
+    let mut checker = Checker {
+        source,
+        format,
+        context,
+        problems: Vec::new(),
+    };
+
+    let root = format.parse(source).map_err(|problem| vec![problem])?;
+
+    let config = serde_yaml::from_value::<Config>(root.clone()).ok();
+    if config.is_none() {
+        checker.parse_parts(&root);
+    }
+    let output = context.output;
+
+    // Devices are checked one by one, so problems of valid devices are found
+    // even when another device cannot be parsed
+    let devices = root.get("devices").and_then(Value::as_sequence);
+    for (i, device) in devices.into_iter().flatten().enumerate() {
+        if let Ok(device) = serde_yaml::from_value::<DeviceConfiguration>(device.clone()) {
+            checker.check_device(&device, i, output);
+        }
+    }
+
+    // Named macros may switch to layers of any device using them
+    let layer_names = context.layer_names(None);
+    let definitions = root.get("macros").and_then(Value::as_mapping);
+    for (name, definition) in definitions.into_iter().flatten() {
+        let name = match name.as_str() {
+            Some(name) => name,
+            None => continue,
+        };
+        if let Ok(definition) = serde_yaml::from_value::<NamedMacro>(definition.clone()) {
+            let path = [Segment::Key("macros"), Segment::Name(name.to_string())];
+            checker.check_macro(&definition.oneof_macro, &path, &layer_names, output);
+        }
+    }
+
+    match config {
+        Some(config) if checker.problems.is_empty() => Ok(config),
+        _ => Err(checker.problems),
+    }
+}
+
+impl Checker<'_> {
+    fn report(&mut self, path: &[Segment], message: String) {
+        self.problems
+            .push(Problem::at(self.source, self.format, path, message));
+    }
+
+    fn parse<T: DeserializeOwned>(&mut self, value: &Value, path: &[Segment]) -> Option<T> {
+        match serde_yaml::from_value(value.clone()) {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                // Unknown variants come with the list of all of them, e.g. all keys
+                let message = e.to_string();
+                let message = match message.find(", expected one of") {
+                    Some(end) => message[..end].to_string(),
+                    None => message,
+                };
+                let mut path = path.to_vec();
+                path.extend(culprit(value, &message));
+                self.report(&path, message);
+                None
+            }
+        }
+    }
+
+    // Finds which parts of configuration cannot be parsed
+    fn parse_parts(&mut self, root: &Value) {
+        if let Some(value) = root.get("output") {
+            let _ = self.parse::<Output>(value, &[Segment::Key("output")]);
+        }
+        let output = self.context.output;
+
+        let definitions = root.get("macros").and_then(Value::as_mapping);
+        for (name, definition) in definitions.into_iter().flatten() {
+            if let Some(name) = name.as_str() {
+                let path = [Segment::Key("macros"), Segment::Name(name.to_string())];
+                let _ = self.parse::<NamedMacro>(definition, &path);
+            }
+        }
+
+        let devices = root.get("devices").and_then(Value::as_sequence);
+        for (i, device) in devices.into_iter().flatten().enumerate() {
+            let path = [Segment::Key("devices"), Segment::Index(i)];
+            if serde_yaml::from_value::<DeviceConfiguration>(device.clone()).is_ok() {
+                continue;
+            }
+
+            // Macros which parse are still checked, so all problems are reported at once
+            let selector = serde_yaml::from_value::<DeviceSelector>(device.clone()).ok();
+            let layer_names = self.context.layer_names(selector.as_ref());
+            let layers = device.get("layers").and_then(Value::as_sequence);
+
+            let found = self.problems.len();
+            let mut macros_path = path.to_vec();
+            macros_path.push(Segment::Key("macros"));
+            self.parse_macros(device, &macros_path, &layer_names, output);
+
+            for (l, layer) in layers.into_iter().flatten().enumerate() {
+                let mut macros_path = path.to_vec();
+                macros_path.extend([
+                    Segment::Key("layers"),
+                    Segment::Index(l),
+                    Segment::Key("macros"),
+                ]);
+                self.parse_macros(layer, &macros_path, &layer_names, output);
+            }
+
+            // Problem is in the device itself, not in its macros
+            if self.problems.len() == found {
+                let _ = self.parse::<DeviceConfiguration>(device, &path);
+            }
+        }
+
+        if self.problems.is_empty() {
+            let _ = self.parse::<Config>(root, &[]);
+        }
+    }
+
+    // Path leads to the macros of the parent
+    fn parse_macros(
+        &mut self,
+        parent: &Value,
+        path: &[Segment],
+        layer_names: &[String],
+        output: Output,
+    ) {
+        let macros = parent.get("macros").and_then(Value::as_sequence);
+        let mut parsed = Vec::new();
+        for (i, value) in macros.into_iter().flatten().enumerate() {
+            let mut macro_path = path.to_vec();
+            macro_path.push(Segment::Index(i));
+            if let Some(conf_macro) = self.parse::<Macro>(value, &macro_path) {
+                parsed.push((i, conf_macro));
+            }
+        }
+        self.check_macros(
+            parsed.iter().map(|(i, m)| (*i, m)),
+            path,
+            layer_names,
+            output,
+        );
+    }
+
+    fn check_device(&mut self, device: &DeviceConfiguration, i: usize, output: Output) {
+        let device_path = [Segment::Key("devices"), Segment::Index(i)];
+
+        // Layers of the same device in other files count as well
+        let layer_names = self.context.layer_names(Some(&DeviceSelector::new(device)));
+
+        let mut path = device_path.to_vec();
+        path.push(Segment::Key("macros"));
+        self.check_macros(
+            device.macros.iter().enumerate(),
+            &path,
+            &layer_names,
+            output,
+        );
+
+        for (l, layer) in device.layers.iter().enumerate() {
+            let mut path = device_path.to_vec();
+            path.extend([
+                Segment::Key("layers"),
+                Segment::Index(l),
+                Segment::Key("macros"),
+            ]);
+            self.check_macros(layer.macros.iter().enumerate(), &path, &layer_names, output);
+        }
+    }
+
+    // Checks macros of one layer, given with their index in the layer
+    fn check_macros<'a>(
+        &mut self,
+        macros: impl Iterator<Item = (usize, &'a Macro)>,
+        path: &[Segment],
+        layer_names: &[String],
+        output: Output,
+    ) {
+        let mut bound: Vec<(Chord, Trigger, Vec<Segment>)> = Vec::new();
+
+        for (i, conf_macro) in macros {
+            let mut path = path.to_vec();
+            path.push(Segment::Index(i));
+
+            let chord = Chord {
+                modifiers: conf_macro
+                    .modifiers
+                    .iter()
+                    .cloned()
+                    .collect::<BTreeSet<_>>(),
+                key: conf_macro.key.clone(),
+            };
+            let trigger = conf_macro.trigger();
+            match bound.iter().find(|(c, t, _)| *c == chord && *t == trigger) {
+                Some((_, _, first)) => {
+                    let first = match self.format.locate(self.source, first) {
+                        Some((line, _)) => format!("line {}", line),
+                        None => path_to_string(first),
+                    };
+                    self.report(
+                        &path,
+                        format!(
+                            "{} is already bound for the same trigger at {}",
+                            chord_name(&chord),
+                            first
+                        ),
+                    );
+                }
+                None => bound.push((chord, trigger, path.clone())),
+            }
+
+            self.check_macro(&conf_macro.oneof_macro, &path, layer_names, output);
+        }
+    }
+
+    fn check_macro(
+        &mut self,
+        conf_macro: &OneOfMacros,
+        path: &[Segment],
+        layer_names: &[String],
+        output: Output,
+    ) {
+        let at = |segments: &[Segment]| -> Vec<Segment> {
+            let mut path = path.to_vec();
+            path.extend_from_slice(segments);
+            path
+        };
+
+        match conf_macro {
+            OneOfMacros::Shell(shell) => self.check_shell(shell, &at(&[Segment::Key("shell")])),
+            OneOfMacros::Shortcut(shortcut) => {
+                self.check_keys(&shortcut.keys, &at(&[Segment::Key("shortcut")]), output)
+            }
+            OneOfMacros::Passthrough(passthrough) => self.check_keys(
+                &passthrough.keys,
+                &at(&[Segment::Key("passthrough")]),
+                output,
+            ),
+            OneOfMacros::Sequence(sequence) => {
+                for (s, step) in sequence.steps.iter().enumerate() {
+                    let step_path = at(&[
+                        Segment::Key("sequence"),
+                        Segment::Key("steps"),
+                        Segment::Index(s),
+                    ]);
+                    self.check_step(&step.oneof_step, &step_path, output);
+                }
+            }
+            OneOfMacros::Layer(layer) => {
+                if !layer_names.contains(&layer.name) {
+                    self.report(
+                        &at(&[Segment::Key("layer"), Segment::Key("name")]),
+                        format!("unknown layer {}", layer.name),
+                    );
+                }
+            }
+            OneOfMacros::Named(name) => {
+                if !self.context.macros.contains(name) {
+                    self.report(
+                        &at(&[Segment::Key("macro")]),
+                        format!("unknown macro {}", name),
+                    );
+                }
+            }
+            OneOfMacros::Type(text) => {
+                self.check_text(&text.text, &at(&[Segment::Key("type")]), output)
+            }
+            OneOfMacros::Panic(_) => {}
+        }
+    }
+
+    fn check_step(&mut self, step: &OneOfSteps, path: &[Segment], output: Output) {
+        let mut at = path.to_vec();
+        match step {
+            OneOfSteps::Shell(shell) => {
+                at.push(Segment::Key("shell"));
+                self.check_shell(shell, &at);
+            }
+            OneOfSteps::Shortcut(shortcut) => {
+                at.push(Segment::Key("shortcut"));
+                self.check_keys(&shortcut.keys, &at, output);
+            }
+            OneOfSteps::KeyDown(key) => {
+                at.push(Segment::Key("key_down"));
+                self.check_key(key, &at, output);
+            }
+            OneOfSteps::KeyUp(key) => {
+                at.push(Segment::Key("key_up"));
+                self.check_key(key, &at, output);
+            }
+            OneOfSteps::Type(text) => {
+                at.push(Segment::Key("type"));
+                self.check_text(&text.text, &at, output);
+            }
+            _ => {}
+        }
+    }
+
+    fn check_keys(&mut self, keys: &[Key], path: &[Segment], output: Output) {
+        for (i, key) in keys.iter().enumerate() {
+            let mut path = path.to_vec();
+            path.extend([Segment::Key("keys"), Segment::Index(i)]);
+            self.check_key(key, &path, output);
+        }
+    }
+
+    fn check_key(&mut self, key: &Key, path: &[Segment], output: Output) {
+        if let Err(e) = output::check_key(output, key) {
+            self.report(path, e.to_string());
+        }
+    }
+
+    fn check_text(&mut self, text: &str, path: &[Segment], output: Output) {
+        if let Err(e) = output::check_text(output, text) {
+            let mut path = path.to_vec();
+            path.push(Segment::Key("text"));
+            self.report(&path, e.to_string());
+        }
+    }
+
+    fn check_shell(&mut self, shell: &ShellMacro, path: &[Segment]) {
+        if let Err(message) = check_command(shell) {
+            let mut path = path.to_vec();
+            path.push(Segment::Key("command"));
+            self.report(&path, message);
+        }
+    }
+}
+
+fn chord_name(chord: &Chord) -> String {
+    let mut keys: Vec<String> = chord.modifiers.iter().map(|k| format!("{:?}", k)).collect();
+    keys.push(format!("{:?}", chord.key));
+    keys.join("+")
+}
+
+// Commands without a directory are looked up in PATH, the one the macro sets in
+// envs if any. Files which cannot be executed are skipped, like when the command is run.
+fn check_command(shell: &ShellMacro) -> Result<(), String> {
+    let command = &shell.command;
+    let files: Vec<PathBuf> =
+        if command.contains('/') || command.contains(std::path::MAIN_SEPARATOR) {
+            vec![PathBuf::from(command)]
+        } else {
+            let paths = match shell.envs.as_ref().and_then(|envs| envs.get("PATH")) {
+                Some(paths) => Some(OsString::from(paths)),
+                None => env::var_os("PATH"),
+            };
+            let dirs: Vec<PathBuf> = paths.iter().flat_map(env::split_paths).collect();
+            let mut files: Vec<PathBuf> = dirs.iter().map(|dir| dir.join(command)).collect();
+            if cfg!(windows) {
+                files.extend(dirs.iter().map(|dir| dir.join(format!("{}.exe", command))));
+            }
+            files
+        };
+
+    let files: Vec<&PathBuf> = files.iter().filter(|file| file.is_file()).collect();
+    if files.is_empty() {
+        Err(format!("command {} not found", command))
+    } else if !files.iter().any(|file| is_executable(file)) {
+        Err(format!("command {} is not executable", command))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(file: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    file.metadata()
+        .is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_file: &Path) -> bool {
+    true
+}
+
+// Path of the value named by a parse error, e.g. unknown variant `Wrong`, below
+// the value which could not be parsed
+fn culprit(value: &Value, message: &str) -> Vec<Segment> {
+    let (keys_only, rest) = if let Some(rest) = message.strip_prefix("unknown field `") {
+        (true, rest)
+    } else if let Some(rest) = message.strip_prefix("unknown variant `") {
+        (false, rest)
+    } else {
+        return Vec::new();
+    };
+    let name = match rest.find('`') {
+        Some(end) => &rest[..end],
+        None => return Vec::new(),
+    };
+
+    fn find(value: &Value, name: &str, keys_only: bool, path: &mut Vec<Segment>) -> bool {
+        let children: Vec<(Segment, &Value)> = match value {
+            Value::String(text) => return !keys_only && text == name,
+            Value::Sequence(items) => items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| (Segment::Index(i), item))
+                .collect(),
+            Value::Mapping(entries) => entries
+                .iter()
+                .filter_map(|(key, item)| Some((Segment::Name(key.as_str()?.to_string()), item)))
+                .collect(),
+            Value::Tagged(tagged) => return find(&tagged.value, name, keys_only, path),
+            _ => return false,
+        };
+
+        for (segment, child) in children {
+            let matches = segment.key() == Some(name);
+            path.push(segment);
+            if matches || find(child, name, keys_only, path) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+
+    let mut path = Vec::new();
+    find(value, name, keys_only, &mut path);
+    path
+}
+
+fn path_to_string(path: &[Segment]) -> String {
+    let mut text = String::new();
+    for segment in path {
+        match segment {
+            Segment::Index(i) => text.push_str(&format!("[{}]", i)),
+            _ => {
+                if !text.is_empty() {
+                    text.push('.');
+                }
+                text.push_str(segment.key().unwrap_or_default());
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"---
+output: uinput
+devices:
+  - vid: 1
+    pid: 2
+    macros:
+      - key: "A"
+        shortcut:
+          keys:
+            - "ControlLeft"
+            - "Wrong"
+      - key: "B"
+        shortcut: { keys: ["C"] }
+      - key: "B"
+        passthrough: { keys: ["D"] }
+  - vid: 3
+    pid: 4
+    layers:
+      - name: "fn"
+        macros:
+        - key: "A"
+          layer: { name: "missing", mode: "hold" }
+        - key: "A"
+          shell:
+            command: "/nonexistent/command"
+            uid: 0
+            args: []
+    macros:
+      - key: "A"
+        passthrough:
+          keys: ["B"]
+"#;
+
+    fn path(segments: &[Segment]) -> Vec<Segment> {
+        segments.to_vec()
+    }
+
+    #[test]
+    fn locates_values() {
+        use Segment::{Index as I, Key as K};
+        let locate = |path: &[Segment]| Format::Yaml.locate(CONFIG, path);
+
+        let keys = path(&[
+            K("devices"),
+            I(0),
+            K("macros"),
+            I(0),
+            K("shortcut"),
+            K("keys"),
+        ]);
+        assert_eq!(locate(&keys), Some((10, 13)));
+
+        let mut second = keys.clone();
+        second.push(I(1));
+        assert_eq!(locate(&second), Some((11, 15)));
+
+        // Flow style is located as well
+        let flow = path(&[
+            K("devices"),
+            I(0),
+            K("macros"),
+            I(1),
+            K("shortcut"),
+            K("keys"),
+            I(0),
+        ]);
+        assert_eq!(locate(&flow), Some((13, 28)));
+
+        // Sequence items indented as much as their key
+        let layer = path(&[
+            K("devices"),
+            I(1),
+            K("layers"),
+            I(0),
+            K("macros"),
+            I(1),
+            K("shell"),
+        ]);
+        assert_eq!(locate(&layer), Some((25, 13)));
+
+        // Keys of the second device are not mixed with the first one
+        let macros = path(&[K("devices"), I(1), K("macros"), I(0), K("passthrough")]);
+        assert_eq!(locate(&macros), Some((31, 11)));
+
+        // Closest parent of a value missing in the file
+        let missing = path(&[K("devices"), I(1), K("macros"), I(3), K("key")]);
+        assert_eq!(locate(&missing), Some((29, 7)));
+        assert_eq!(locate(&[K("missing")]), None);
+    }
+
+    fn problems(source: &str) -> Vec<String> {
+        let root = serde_yaml::from_str::<Value>(source).ok();
+        match validate(source, Format::Yaml, &Context::new(root.iter())) {
+            Ok(_) => Vec::new(),
+            Err(problems) => problems.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn reports_all_problems() {
+        // Rest of the first device and the second device are checked even though
+        // the first one cannot be parsed
+        assert_eq!(
+            problems(CONFIG),
+            [
+                "11:15: devices[0].macros[0].shortcut.keys[1]: unknown variant `Wrong`",
+                "14:9: devices[0].macros[2]: B is already bound for the same trigger at line 12",
+                "22:26: devices[1].layers[0].macros[0].layer.name: unknown layer missing",
+                "23:11: devices[1].layers[0].macros[1]: A is already bound for the same trigger at line 21",
+                "25:22: devices[1].layers[0].macros[1].shell.command: command /nonexistent/command not found",
+            ]
+        );
+
+        let found = problems("devices:\n  - vid: 1\n    pid: [2\n");
+        assert_eq!(found.len(), 1);
+        assert!(found[0].starts_with("4:1: "), "{}", found[0]);
+    }
+
+    #[test]
+    fn checks_texts_and_commands() {
+        let config = r#"
+output: uinput
+devices:
+  - vid: 1
+    pid: 2
+    macros:
+      - key: "A"
+        type: { text: "ok é\n" }
+      - key: "B"
+        type: { text: "bell \a" }
+      - key: "C"
+        sequence:
+          steps:
+            - type: { text: "\e" }
+      - key: "D"
+        shell: { command: "sh", uid: 0, args: [], envs: { PATH: "/nonexistent" } }
+      - key: "E"
+        shell: { command: "sh", uid: 0, args: [], envs: { PATH: "/nonexistent:/bin" } }
+      - key: "F"
+        shell: { command: "/etc/passwd", uid: 0, args: [] }
+"#;
+        assert_eq!(
+            problems(config),
+            [
+                "10:23: devices[0].macros[1].type.text: character '\\u{7}' cannot be typed by uinput output",
+                "14:29: devices[0].macros[2].sequence.steps[0].type.text: character '\\u{1b}' cannot be typed by uinput output",
+                "16:27: devices[0].macros[3].shell.command: command sh not found",
+                "20:27: devices[0].macros[5].shell.command: command /etc/passwd is not executable",
+            ]
+        );
+    }
+}
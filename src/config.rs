@@ -1,38 +1,406 @@
 
 use serde;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use crate::binding;
+use crate::check;
+use crate::error::Error;
+use crate::format::Format;
+use crate::include;
 use crate::keyboard;
 use crate::macros::shell::ShellMacro;
+use crate::output::MouseButton;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct Shortcut {
     pub keys : Vec<keyboard::Key>
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
+pub struct Passthrough {
+    pub keys: Vec<keyboard::Key>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Text {
+    pub text: String,
+    // Delay after every key event
+    pub delay_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LayerMode {
+    // Layer is active while the key is held
+    #[serde(rename = "hold")]
+    Hold,
+    // Every trigger switches the layer on or off
+    #[serde(rename = "toggle")]
+    Toggle,
+    // Layer is active for the next key press only
+    #[serde(rename = "oneshot")]
+    OneShot,
+}
+
+// Cancels all running macros of the application
+#[derive(Deserialize, Clone)]
+pub struct Panic {}
+
+// Wheel steps, positive y scrolls up and positive x scrolls right
+#[derive(Deserialize, Clone)]
+pub struct Scroll {
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+}
+
+// Absolute pointer position in pixels
+#[derive(Deserialize, Clone)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Layer {
+    pub name: String,
+    pub mode: LayerMode,
+}
+
+#[derive(Deserialize, Clone)]
+pub enum OneOfSteps {
+    #[serde(rename = "shell")]
+    Shell(ShellMacro),
+    #[serde(rename = "shortcut")]
+    Shortcut(Shortcut),
+    #[serde(rename = "type")]
+    Type(Text),
+    #[serde(rename = "key_down")]
+    KeyDown(keyboard::Key),
+    #[serde(rename = "key_up")]
+    KeyUp(keyboard::Key),
+    #[serde(rename = "button_down")]
+    ButtonDown(MouseButton),
+    #[serde(rename = "button_up")]
+    ButtonUp(MouseButton),
+    #[serde(rename = "scroll")]
+    Scroll(Scroll),
+    #[serde(rename = "move_to")]
+    MoveTo(Position),
+    // Milliseconds or text with unit, e.g. 250ms or 1s
+    #[serde(rename = "wait", deserialize_with = "deserialize_duration")]
+    Wait(Duration),
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Step {
+    #[serde(flatten)]
+    pub oneof_step: OneOfSteps,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Sequence {
+    pub steps: Vec<Step>,
+}
+
+#[derive(Deserialize, Clone)]
 pub enum OneOfMacros {
     #[serde(rename = "shell")]
     Shell(ShellMacro), 
     #[serde(rename = "shortcut")]
-    Shortcut(Shortcut)
+    Shortcut(Shortcut),
+    #[serde(rename = "passthrough")]
+    Passthrough(Passthrough),
+    #[serde(rename = "type")]
+    Type(Text),
+    #[serde(rename = "sequence")]
+    Sequence(Sequence),
+    #[serde(rename = "layer")]
+    Layer(Layer),
+    #[serde(rename = "panic")]
+    Panic(Panic),
+    // Macro defined by name in `macros` of any configuration file
+    #[serde(rename = "macro")]
+    Named(String),
+}
+
+// Macro defined once and bound to keys of any device with `macro: <name>`
+#[derive(Deserialize, Clone)]
+pub struct NamedMacro {
+    #[serde(flatten)]
+    pub oneof_macro: OneOfMacros,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModifiersMatch {
+    // Held modifiers must be exactly the listed ones
+    #[default]
+    #[serde(rename = "exact")]
+    Exact,
+    // Listed modifiers must be held, others are allowed as well
+    #[serde(rename = "subset")]
+    Subset,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Trigger {
+    #[serde(rename = "press")]
+    Press,
+    #[default]
+    #[serde(rename = "release")]
+    Release,
+    #[serde(rename = "hold")]
+    Hold,
+    #[serde(rename = "double_tap")]
+    DoubleTap,
+}
+
+// What happens when the macro is triggered while it is still running
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overlap {
+    // Run again after the current run finishes
+    #[default]
+    #[serde(rename = "queue")]
+    Queue,
+    // Ignore the trigger
+    #[serde(rename = "drop")]
+    Drop,
+    // Cancel the current run and start over
+    #[serde(rename = "restart")]
+    Restart,
+    // Cancel the current run, second trigger works as a stop button
+    #[serde(rename = "cancel")]
+    Cancel,
+    // Start another run alongside the current one
+    #[serde(rename = "parallel")]
+    Parallel,
 }
 
 #[derive(Deserialize)]
 pub struct Macro {
     pub key: keyboard::Key,
+    #[serde(default)]
+    pub modifiers: Vec<keyboard::Key>,
+    #[serde(default)]
+    pub modifiers_match: ModifiersMatch,
+    #[serde(default)]
+    pub trigger: Trigger,
+    // How long the key has to be held for hold trigger
+    pub threshold_ms: Option<u64>,
+    // Time between taps for double_tap trigger
+    pub window_ms: Option<u64>,
+    #[serde(default)]
+    pub overlap: Overlap,
     #[serde(flatten)]
     pub oneof_macro : OneOfMacros,
 }
 
+// Report IDs of the collections on devices which prefix reports with an ID
+#[derive(Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct ReportIds {
+    pub keyboard: Option<u8>,
+    pub consumer: Option<u8>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    // Report descriptor when available, boot protocol otherwise
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    // 6KRO key array
+    #[serde(rename = "boot")]
+    Boot,
+    // NKRO bitmap of keys
+    #[serde(rename = "bitmap")]
+    Bitmap,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Input {
+    // HID reports read through hidapi, keys are still delivered to the system
+    #[default]
+    #[serde(rename = "hidraw")]
+    Hidraw,
+    // Linux input event devices grabbed exclusively
+    #[serde(rename = "evdev")]
+    Evdev,
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Millis(u64),
+        Text(String),
+    }
+
+    let text = match Value::deserialize(deserializer)? {
+        Value::Millis(ms) => return Ok(Duration::from_millis(ms)),
+        Value::Text(text) => text,
+    };
+
+    let trimmed = text.trim();
+    let parsed = if let Some(ms) = trimmed.strip_suffix("ms") {
+        ms.trim().parse().map(Duration::from_millis)
+    } else if let Some(s) = trimmed.strip_suffix('s') {
+        s.trim().parse().map(Duration::from_secs)
+    } else {
+        trimmed.parse().map(Duration::from_millis)
+    };
+
+    parsed.map_err(|_| serde::de::Error::custom(format!("invalid duration {}", text)))
+}
+
+const DEFAULT_TYPE_DELAY_MS: u64 = 10;
+const DEFAULT_HOLD_MS: u64 = 500;
+const DEFAULT_DOUBLE_TAP_MS: u64 = 300;
+const DEFAULT_RECONNECT_DELAY_MS: u64 = 500;
+const DEFAULT_RECONNECT_MAX_DELAY_MS: u64 = 30000;
+
+impl Text {
+    pub fn delay(&self) -> Duration {
+        Duration::from_millis(self.delay_ms.unwrap_or(DEFAULT_TYPE_DELAY_MS))
+    }
+}
+
+impl Macro {
+    pub fn trigger(&self) -> binding::Trigger {
+        match self.trigger {
+            Trigger::Press => binding::Trigger::Press,
+            Trigger::Release => binding::Trigger::Release,
+            Trigger::Hold => binding::Trigger::Hold(Duration::from_millis(
+                self.threshold_ms.unwrap_or(DEFAULT_HOLD_MS),
+            )),
+            Trigger::DoubleTap => binding::Trigger::DoubleTap(Duration::from_millis(
+                self.window_ms.unwrap_or(DEFAULT_DOUBLE_TAP_MS),
+            )),
+        }
+    }
+}
+
+// Retries after errors of an open device. Disconnected device is waited for
+// until it is plugged in again, permission errors are not retried.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Reconnect {
+    // Consecutive failed attempts before the device is given up, unlimited by default
+    pub max_retries: Option<u32>,
+    // Delay before the first retry, doubled with every failed attempt
+    pub delay_ms: Option<u64>,
+    pub max_delay_ms: Option<u64>,
+}
+
+impl Reconnect {
+    pub fn delay(&self) -> Duration {
+        Duration::from_millis(self.delay_ms.unwrap_or(DEFAULT_RECONNECT_DELAY_MS))
+    }
+
+    pub fn max_delay(&self) -> Duration {
+        Duration::from_millis(self.max_delay_ms.unwrap_or(DEFAULT_RECONNECT_MAX_DELAY_MS))
+    }
+}
+
+// Name of the layer formed by device macros
+pub const BASE_LAYER_NAME: &str = "base";
+
+#[derive(Deserialize)]
+pub struct LayerConfiguration {
+    pub name: String,
+    pub macros: Vec<Macro>,
+}
+
 #[derive(Deserialize)]
 pub struct DeviceConfiguration {
     pub vid: u16,
     pub pid: u16,
+    // Optional selectors telling apart devices with the same VID and PID
+    pub serial: Option<String>,
+    pub interface_number: Option<i32>,
+    pub usage_page: Option<u16>,
+    pub usage: Option<u16>,
+    pub path: Option<String>,
+    // Settings left out are the defaults, unless an included file sets them
+    pub input: Option<Input>,
+    pub report_ids: Option<ReportIds>,
+    pub report_format: Option<ReportFormat>,
+    pub reconnect: Option<Reconnect>,
     pub macros: Vec<Macro>,
+    // Layers stacked above device macros, later ones have priority
+    #[serde(default)]
+    pub layers: Vec<LayerConfiguration>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Output {
+    // XTest on X11 sessions, native API on Windows
+    #[default]
+    #[serde(rename = "rdev")]
+    Rdev,
+    // Virtual keyboard created through /dev/uinput (Linux), works on Wayland as well
+    #[serde(rename = "uinput")]
+    Uinput,
 }
 
 #[derive(Deserialize)]
 pub struct Config {
+    // Where macros send their key events
+    pub output: Option<Output>,
+    // Macros referenced by name from devices of all files
+    #[serde(default)]
+    pub macros: HashMap<String, NamedMacro>,
+    #[serde(default)]
     pub devices: Vec<DeviceConfiguration>,
+    // Files the configuration was merged from, filled in by load
+    #[serde(skip)]
+    pub files: Vec<PathBuf>,
+}
+
+impl DeviceConfiguration {
+    pub fn input(&self) -> Input {
+        self.input.unwrap_or_default()
+    }
+
+    pub fn report_ids(&self) -> ReportIds {
+        self.report_ids.clone().unwrap_or_default()
+    }
+
+    pub fn report_format(&self) -> ReportFormat {
+        self.report_format.unwrap_or_default()
+    }
+
+    pub fn reconnect(&self) -> Reconnect {
+        self.reconnect.unwrap_or_default()
+    }
+}
+
+impl Config {
+    pub fn output(&self) -> Output {
+        self.output.unwrap_or_default()
+    }
+}
+
+// Reads and validates configuration file with the files it includes, all problems
+// are reported with their file and position
+pub fn load(path: &str, format: Format) -> Result<Config, Error> {
+    let (files, mut problems) = include::read(Path::new(path), format)?;
+    let context = check::Context::new(files.iter().filter_map(|f| f.value.as_ref()));
+
+    let mut configs = Vec::new();
+    for file in &files {
+        match check::validate(&file.source, file.format, &context) {
+            Ok(config) => configs.push(config),
+            Err(found) => problems.extend(found.iter().map(|p| p.in_file(&file.path))),
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(Error::Config(problems.join("\n")));
+    }
+    include::merge(&files, configs).map_err(|problems| Error::Config(problems.join("\n")))
 }
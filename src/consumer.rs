@@ -0,0 +1,69 @@
+use crate::keyboard::{diff_keys, Key, KeyEvent};
+
+// Decoder of Consumer Control reports (media keys).
+// Report body is an array of 16-bit little endian usages, zero meaning no key.
+pub struct ConsumerControl {
+    previous_keys: Vec<Key>,
+}
+
+impl ConsumerControl {
+    pub fn new() -> ConsumerControl {
+        ConsumerControl {
+            previous_keys: Vec::new(),
+        }
+    }
+
+    // Decodes consumer report (without report ID)
+    pub fn events(&mut self, report: &[u8]) -> Vec<KeyEvent> {
+        let mut keys = Vec::new();
+
+        for chunk in report.chunks_exact(2) {
+            let usage = u16::from_le_bytes([chunk[0], chunk[1]]);
+            if usage == 0 {
+                continue;
+            }
+
+            match Key::try_consumer_from(usage) {
+                Ok(key) => keys.push(key),
+                Err(_) => println!("Unknown consumer key with usage {:#06x}", usage),
+            }
+        }
+
+        diff_keys(&mut self.previous_keys, keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::summary;
+
+    #[test]
+    fn decodes_and_releases_media_keys() {
+        let mut consumer = ConsumerControl::new();
+
+        // Volume Up (0xE9) and Play/Pause (0xCD)
+        let events = consumer.events(&[0xE9, 0x00, 0xCD, 0x00]);
+        assert_eq!(
+            summary(events),
+            vec![(Key::VolumeUp, true), (Key::PlayPause, true)]
+        );
+
+        // Unknown usage is skipped, keys next to it stay held
+        let events = consumer.events(&[0xE9, 0x00, 0xFF, 0x0F, 0xCD, 0x00]);
+        assert!(events.is_empty());
+
+        // Volume Up released, Mail (0x18A) pressed
+        let events = consumer.events(&[0x8A, 0x01, 0xCD, 0x00]);
+        assert_eq!(
+            summary(events),
+            vec![(Key::Mail, true), (Key::VolumeUp, false)]
+        );
+
+        let events = consumer.events(&[0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            summary(events),
+            vec![(Key::Mail, false), (Key::PlayPause, false)]
+        );
+    }
+}
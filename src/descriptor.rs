@@ -0,0 +1,418 @@
+// Parser of HID report descriptors.
+// Only input reports are described, output and feature reports are skipped.
+use std::collections::HashMap;
+
+pub const PAGE_KEYBOARD: u16 = 0x07;
+pub const PAGE_CONSUMER: u16 = 0x0C;
+
+const ITEM_MAIN: u8 = 0;
+const ITEM_GLOBAL: u8 = 1;
+const ITEM_LOCAL: u8 = 2;
+const ITEM_LONG: u8 = 0xFE;
+
+// Main items
+const TAG_INPUT: u8 = 0x8;
+
+// Global items
+const TAG_USAGE_PAGE: u8 = 0x0;
+const TAG_LOGICAL_MIN: u8 = 0x1;
+const TAG_LOGICAL_MAX: u8 = 0x2;
+const TAG_REPORT_SIZE: u8 = 0x7;
+const TAG_REPORT_ID: u8 = 0x8;
+const TAG_REPORT_COUNT: u8 = 0x9;
+const TAG_PUSH: u8 = 0xA;
+const TAG_POP: u8 = 0xB;
+
+// Local items
+const TAG_USAGE: u8 = 0x0;
+const TAG_USAGE_MIN: u8 = 0x1;
+const TAG_USAGE_MAX: u8 = 0x2;
+
+// Input item flags
+const FLAG_CONSTANT: u32 = 0x01;
+const FLAG_VARIABLE: u32 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub page: u16,
+    pub id: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldKind {
+    // Every element is one usage, non-zero value means pressed (bitmaps)
+    Variable,
+    // Every element holds index of the pressed usage (key arrays)
+    Array,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub kind: FieldKind,
+    pub bit_offset: usize,
+    pub bit_size: usize,
+    pub count: usize,
+    pub logical_min: i32,
+    pub logical_max: i32,
+    usages: Vec<Usage>,
+    usage_range: Option<(Usage, u16)>,
+}
+
+impl Field {
+    // Usage of n-th element (variable field) or n-th index (array field)
+    pub fn usage(&self, index: usize) -> Option<Usage> {
+        if index < self.usages.len() {
+            return Some(self.usages[index]);
+        }
+
+        if let Some((min, max)) = self.usage_range {
+            let offset = index - self.usages.len();
+            let id = min.id as usize + offset;
+            if id <= max as usize {
+                return Some(Usage {
+                    page: min.page,
+                    id: id as u16,
+                });
+            }
+            return None;
+        }
+
+        // Variable fields reuse the last usage for remaining elements
+        match self.kind {
+            FieldKind::Variable => self.usages.last().copied(),
+            FieldKind::Array => None,
+        }
+    }
+
+    // Reads n-th element from report body
+    pub fn value(&self, body: &[u8], index: usize) -> Option<u32> {
+        let offset = self.bit_offset + index * self.bit_size;
+        let mut value = 0u32;
+
+        for bit in 0..self.bit_size.min(32) {
+            let position = offset + bit;
+            let byte = body.get(position / 8)?;
+            if byte & (1 << (position % 8)) != 0 {
+                value |= 1 << bit;
+            }
+        }
+
+        Some(value)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    // Zero when device does not use report IDs
+    pub id: u8,
+    pub fields: Vec<Field>,
+    // Size of the report body in bits (without report ID)
+    pub bit_size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReportLayout {
+    pub reports: Vec<Report>,
+}
+
+impl ReportLayout {
+    pub fn uses_report_ids(&self) -> bool {
+        self.reports.iter().any(|r| r.id != 0)
+    }
+
+    pub fn report(&self, id: u8) -> Option<&Report> {
+        self.reports.iter().find(|r| r.id == id)
+    }
+
+    // Buffer size needed to read the largest input report
+    pub fn max_report_size(&self) -> usize {
+        let body = self
+            .reports
+            .iter()
+            .map(|r| r.bit_size.div_ceil(8))
+            .max()
+            .unwrap_or(0);
+
+        body + 1
+    }
+
+    // True when any report carries keys we are able to decode
+    pub fn has_keys(&self) -> bool {
+        self.reports.iter().any(|r| {
+            r.fields.iter().any(|f| {
+                (0..f.count.max(1)).any(|i| match f.usage(i) {
+                    Some(u) => u.page == PAGE_KEYBOARD || u.page == PAGE_CONSUMER,
+                    None => false,
+                })
+            })
+        })
+    }
+}
+
+#[derive(Clone, Default)]
+struct GlobalState {
+    usage_page: u16,
+    logical_min: i32,
+    logical_max: i32,
+    report_size: usize,
+    report_count: usize,
+    report_id: u8,
+}
+
+#[derive(Default)]
+struct LocalState {
+    usages: Vec<Usage>,
+    usage_min: Option<u32>,
+    usage_max: Option<u32>,
+}
+
+pub fn parse(descriptor: &[u8]) -> Result<ReportLayout, String> {
+    let mut global = GlobalState::default();
+    let mut stack: Vec<GlobalState> = Vec::new();
+    let mut local = LocalState::default();
+    let mut offsets: HashMap<u8, usize> = HashMap::new();
+    let mut reports: Vec<Report> = Vec::new();
+
+    let mut pos = 0;
+    while pos < descriptor.len() {
+        let prefix = descriptor[pos];
+
+        if prefix == ITEM_LONG {
+            let size = *descriptor.get(pos + 1).ok_or("truncated long item")? as usize;
+            pos += 3 + size;
+            continue;
+        }
+
+        let size = match prefix & 0x03 {
+            3 => 4,
+            s => s as usize,
+        };
+        let item_type = (prefix >> 2) & 0x03;
+        let tag = prefix >> 4;
+
+        let data = descriptor
+            .get(pos + 1..pos + 1 + size)
+            .ok_or_else(|| format!("truncated item at offset {}", pos))?;
+        let value = unsigned(data);
+        pos += 1 + size;
+
+        match item_type {
+            ITEM_MAIN => {
+                if tag == TAG_INPUT {
+                    let offset = offsets.entry(global.report_id).or_insert(0);
+                    let bits = global.report_size * global.report_count;
+
+                    if value & FLAG_CONSTANT == 0 {
+                        let field = Field {
+                            kind: if value & FLAG_VARIABLE != 0 {
+                                FieldKind::Variable
+                            } else {
+                                FieldKind::Array
+                            },
+                            bit_offset: *offset,
+                            bit_size: global.report_size,
+                            count: global.report_count,
+                            logical_min: global.logical_min,
+                            logical_max: global.logical_max,
+                            usages: local.usages.clone(),
+                            usage_range: usage_range(&local, global.usage_page),
+                        };
+
+                        match reports.iter_mut().find(|r| r.id == global.report_id) {
+                            Some(report) => report.fields.push(field),
+                            None => reports.push(Report {
+                                id: global.report_id,
+                                fields: vec![field],
+                                bit_size: 0,
+                            }),
+                        }
+                    }
+
+                    *offset += bits;
+                }
+                // Every main item resets local state
+                local = LocalState::default();
+            }
+            ITEM_GLOBAL => match tag {
+                TAG_USAGE_PAGE => global.usage_page = value as u16,
+                TAG_LOGICAL_MIN => global.logical_min = signed(data),
+                TAG_LOGICAL_MAX => {
+                    // Logical maximum is unsigned when minimum is not negative
+                    global.logical_max = if global.logical_min < 0 {
+                        signed(data)
+                    } else {
+                        value as i32
+                    }
+                }
+                TAG_REPORT_SIZE => global.report_size = value as usize,
+                TAG_REPORT_COUNT => global.report_count = value as usize,
+                TAG_REPORT_ID => {
+                    if value == 0 || value > u8::MAX as u32 {
+                        return Err(format!("invalid report ID {}", value));
+                    }
+                    global.report_id = value as u8;
+                }
+                TAG_PUSH => stack.push(global.clone()),
+                TAG_POP => global = stack.pop().ok_or("pop without push")?,
+                _ => {}
+            },
+            ITEM_LOCAL => match tag {
+                TAG_USAGE => local.usages.push(extended_usage(value, size, global.usage_page)),
+                TAG_USAGE_MIN => local.usage_min = Some(value),
+                TAG_USAGE_MAX => local.usage_max = Some(value),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    for report in reports.iter_mut() {
+        report.bit_size = offsets.get(&report.id).copied().unwrap_or(0);
+    }
+
+    Ok(ReportLayout { reports })
+}
+
+fn usage_range(local: &LocalState, page: u16) -> Option<(Usage, u16)> {
+    match (local.usage_min, local.usage_max) {
+        (Some(min), Some(max)) => Some((
+            Usage {
+                page: if min > 0xFFFF { (min >> 16) as u16 } else { page },
+                id: min as u16,
+            },
+            max as u16,
+        )),
+        _ => None,
+    }
+}
+
+// 4 byte usages carry their own usage page in the upper half
+fn extended_usage(value: u32, size: usize, page: u16) -> Usage {
+    if size == 4 {
+        Usage {
+            page: (value >> 16) as u16,
+            id: value as u16,
+        }
+    } else {
+        Usage {
+            page,
+            id: value as u16,
+        }
+    }
+}
+
+fn unsigned(data: &[u8]) -> u32 {
+    data.iter()
+        .enumerate()
+        .fold(0, |acc, (n, b)| acc | (*b as u32) << (8 * n))
+}
+
+fn signed(data: &[u8]) -> i32 {
+    match data.len() {
+        1 => data[0] as i8 as i32,
+        2 => i16::from_le_bytes([data[0], data[1]]) as i32,
+        4 => i32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+        _ => 0,
+    }
+}
+
+// Reads report descriptor of the device interface from sysfs
+#[cfg(target_os = "linux")]
+pub fn read_report_descriptor(vid: u16, pid: u16, interface: i32) -> Option<Vec<u8>> {
+    use std::fs;
+
+    let hid_id = format!(":{:08X}:{:08X}", vid, pid);
+    let phys = format!("/input{}", interface);
+
+    for entry in fs::read_dir("/sys/bus/hid/devices").ok()?.flatten() {
+        let uevent = match fs::read_to_string(entry.path().join("uevent")) {
+            Ok(u) => u,
+            Err(_) => continue,
+        };
+
+        let id_matches = uevent
+            .lines()
+            .any(|l| l.starts_with("HID_ID=") && l.ends_with(&hid_id));
+        let interface_matches = interface < 0
+            || uevent
+                .lines()
+                .any(|l| l.starts_with("HID_PHYS=") && l.ends_with(&phys));
+
+        if id_matches && interface_matches {
+            if let Ok(descriptor) = fs::read(entry.path().join("report_descriptor")) {
+                return Some(descriptor);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_report_descriptor(_vid: u16, _pid: u16, _interface: i32) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Boot keyboard (report ID 1) followed by consumer control (report ID 3)
+    const COMPOSITE: [u8; 72] = [
+        0x05, 0x01, 0x09, 0x06, 0xA1, 0x01, 0x85, 0x01, // Keyboard, report ID 1
+        0x05, 0x07, 0x19, 0xE0, 0x29, 0xE7, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x08,
+        0x81, 0x02, // modifiers bitmap
+        0x95, 0x01, 0x75, 0x08, 0x81, 0x01, // reserved byte
+        0x95, 0x06, 0x75, 0x08, 0x15, 0x00, 0x25, 0x65, 0x05, 0x07, 0x19, 0x00, 0x29, 0x65,
+        0x81, 0x00, // key array
+        0xC0, 0x05, 0x0C, 0x09, 0x01, 0xA1, 0x01, 0x85, 0x03, // Consumer, report ID 3
+        0x15, 0x00, 0x26, 0xFF, 0x03, 0x19, 0x00, 0x2A, 0xFF, 0x03, 0x75, 0x10, 0x95, 0x01,
+        0x81, 0x00, 0xC0,
+    ];
+
+    #[test]
+    fn parses_composite_keyboard() {
+        let layout = parse(&COMPOSITE).unwrap();
+        assert!(layout.uses_report_ids());
+        assert_eq!(layout.reports.len(), 2);
+        assert_eq!(layout.max_report_size(), 9);
+
+        let keyboard = layout.report(1).unwrap();
+        assert_eq!(keyboard.bit_size, 64);
+        assert_eq!(keyboard.fields.len(), 2);
+
+        let modifiers = &keyboard.fields[0];
+        assert_eq!(modifiers.kind, FieldKind::Variable);
+        assert_eq!((modifiers.bit_offset, modifiers.bit_size, modifiers.count), (0, 1, 8));
+        assert_eq!(modifiers.usage(3), Some(Usage { page: PAGE_KEYBOARD, id: 0xE3 }));
+
+        let keys = &keyboard.fields[1];
+        assert_eq!(keys.kind, FieldKind::Array);
+        assert_eq!((keys.bit_offset, keys.bit_size, keys.count), (16, 8, 6));
+        assert_eq!(keys.logical_max, 0x65);
+
+        let consumer = layout.report(3).unwrap();
+        assert_eq!(consumer.bit_size, 16);
+        assert_eq!(consumer.fields[0].logical_max, 0x3FF);
+        assert_eq!(
+            consumer.fields[0].usage(0xE9),
+            Some(Usage { page: PAGE_CONSUMER, id: 0xE9 })
+        );
+    }
+
+    #[test]
+    fn reads_field_values() {
+        let layout = parse(&COMPOSITE).unwrap();
+        let keys = &layout.report(1).unwrap().fields[1];
+        let body = [0x02, 0x00, 0x04, 0x05, 0x00, 0x00, 0x00, 0x00];
+        assert_eq!(keys.value(&body, 0), Some(0x04));
+        assert_eq!(keys.value(&body, 1), Some(0x05));
+        assert_eq!(keys.value(&body, 2), Some(0x00));
+        assert_eq!(keys.value(&body[..3], 2), None);
+    }
+
+    #[test]
+    fn rejects_truncated_descriptor() {
+        assert!(parse(&[0x05, 0x01, 0x09]).is_err());
+    }
+}
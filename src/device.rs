@@ -1,111 +1,625 @@
-use crate::{keyboard, macros::Macro};
-use hidapi::{HidDevice, HidError};
+use crate::{
+    binding::{self, Action, MacrosBinding},
+    config::Reconnect,
+    error::Error,
+    hotplug::Hotplug,
+    input::{DeviceSelector, InputBackend, InputError, InputReader},
+    keyboard,
+    layer::LayerStack,
+    reconnect::Backoff,
+    stop::StopFlag,
+    trigger::{Gesture, GestureType, Triggers},
+};
 use std::{
-    collections::HashMap,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc, Mutex,
-    },
-    thread,
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-pub type MacrosBinding = HashMap<keyboard::Key, Box<dyn Macro + Send>>;
+// Longest time to block in read, so stop flag is checked regularly
+const READ_TIMEOUT: Duration = Duration::from_millis(2500);
+
+// Layers of a device, base layer first. Shared with the app, which swaps them
+// on configuration reload while the device stays open.
+#[derive(Clone)]
+pub struct SharedLayers(Arc<Mutex<Arc<Vec<MacrosBinding>>>>);
+
+impl SharedLayers {
+    pub fn new(layers: Vec<MacrosBinding>) -> SharedLayers {
+        SharedLayers(Arc::new(Mutex::new(Arc::new(layers))))
+    }
+
+    pub fn get(&self) -> Arc<Vec<MacrosBinding>> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn replace(&self, layers: Vec<MacrosBinding>) {
+        *self.0.lock().unwrap() = Arc::new(layers);
+    }
+}
 
 pub struct Device {
-    vid: u16,
-    pid: u16,
-    macros: MacrosBinding,
-    hid_api: Arc<Mutex<hidapi::HidApi>>,
+    selector: DeviceSelector,
+    input: Box<dyn InputBackend>,
+    hotplug: Arc<Hotplug>,
+    reconnect: Reconnect,
+    layers: SharedLayers,
 }
 
 impl Device {
     pub fn new(
-        vid: u16,
-        pid: u16,
-        macros: MacrosBinding,
-        hid_api: Arc<Mutex<hidapi::HidApi>>,
+        selector: DeviceSelector,
+        input: Box<dyn InputBackend>,
+        hotplug: Arc<Hotplug>,
+        reconnect: Reconnect,
+        layers: Vec<MacrosBinding>,
     ) -> Device {
         Device {
-            vid: vid,
-            pid: pid,
-            macros: macros,
-            hid_api: hid_api,
+            selector,
+            input,
+            hotplug,
+            reconnect,
+            layers: SharedLayers::new(layers),
         }
     }
 
-    pub fn listen(&mut self, stop: Arc<AtomicBool>) {
-        let wait_duration = std::time::Duration::from_secs(5);
+    pub fn layers(&self) -> SharedLayers {
+        self.layers.clone()
+    }
+
+    // Runs until stopped, error means the device was given up
+    pub fn listen(&mut self, stop: StopFlag) -> Result<(), Error> {
+        let mut backoff = Backoff::new(self.reconnect);
 
-        while !stop.load(Ordering::Relaxed) {
+        while !stop.is_stopped() {
+            // Read before looking for the device, so a device plugged in meanwhile is not missed
+            let changes = self.hotplug.changes();
             if !self.is_present() {
-                thread::sleep(wait_duration);
+                // Missing device is waited for without limit, retries are for a present one
+                self.hotplug.wait(changes, &stop);
                 continue;
             }
 
-            if let Ok(hid_device) = self.open_device() {
-                match self.process_events(hid_device, &stop) {
-                    Ok(_) => continue,
-                    Err(_) =>{ 
-                        println!("Communication error. Device will not be listened to anymore.");
-                        return;
-                    }
+            let result = match self.input.open(&self.selector) {
+                Ok(mut reader) => self.process_events(reader.as_mut(), &stop, &mut backoff),
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(_) => continue,
+                Err(InputError::Disconnected(_)) if !self.is_present() => {
+                    println!("{} disconnected, waiting for it", self.selector);
                 }
-            } else {
-                println!("cannot open device: {} {}", self.vid, self.pid);
-                thread::sleep(wait_duration);
-                continue;
+                Err(e @ InputError::PermissionDenied(_)) => {
+                    println!("Run as root or grant access to the device with a udev rule.");
+                    return Err(self.error(e));
+                }
+                Err(e) => match backoff.fail() {
+                    Some(delay) => {
+                        println!(
+                            "Error on {} ({}), retrying in {:?}",
+                            self.selector, e, delay
+                        );
+                        stop.sleep(delay);
+                    }
+                    None => {
+                        println!("Giving up after {} retries.", backoff.failures());
+                        return Err(self.error(e));
+                    }
+                },
             }
         }
+
+        Ok(())
     }
 
-    pub fn open_device(&mut self) -> Result<HidDevice, HidError> {
-        let api = self.hid_api.lock().unwrap();
-        api.open(self.vid, self.pid)
-        //api.open_serial(vid, pid, sn)
+    fn error(&self, source: InputError) -> Error {
+        Error::Device {
+            device: self.selector.to_string(),
+            source,
+        }
     }
 
     pub fn is_present(&mut self) -> bool {
-        let mut api = self.hid_api.lock().unwrap();
-        api.refresh_devices().unwrap();
+        self.input
+            .enumerate()
+            .iter()
+            .any(|d| self.selector.matches(d))
+    }
+
+    // Runs macros for key events of the opened device
+    fn process_events(
+        &self,
+        reader: &mut dyn InputReader,
+        stop: &StopFlag,
+        backoff: &mut Backoff,
+    ) -> Result<(), InputError> {
+        let mut held_modifiers = BTreeSet::new();
+        let mut bindings = self.layers.get();
+        let mut triggers = Triggers::new();
+        let mut layers = LayerStack::new(bindings.len());
+
+        while !stop.is_stopped() {
+            // Wake up in time for pending hold and double tap gestures
+            let timeout = triggers
+                .next_deadline(Instant::now())
+                .map_or(READ_TIMEOUT, |d| d.min(READ_TIMEOUT));
+
+            let events = reader.read_timeout(timeout)?;
+            // Device works, failures before do not count anymore
+            backoff.reset();
+            let now = Instant::now();
+
+            // Layers were reloaded, pending gestures and active layers belong to the old ones
+            let current = self.layers.get();
+            if !Arc::ptr_eq(&current, &bindings) {
+                bindings = current;
+                triggers = Triggers::new();
+                layers = LayerStack::new(bindings.len());
+            }
+
+            // Modifiers are held for all keys of the report they are pressed in, and
+            // released only after keys of the report they are released in
+            let mut released = Vec::new();
+            for event in events.iter().filter(|e| e.key.is_modifier()) {
+                if event.event_type == keyboard::KeyEventType::PRESSED {
+                    held_modifiers.insert(event.key.clone());
+                } else {
+                    released.push(event.key.clone());
+                }
+            }
+
+            for event in events {
+                let mut modifiers = held_modifiers.clone();
+                modifiers.remove(&event.key);
+
+                let found = match event.event_type {
+                    keyboard::KeyEventType::PRESSED => {
+                        let layer = layers.press(&bindings, &event.key, &modifiers);
+                        binding::find(&bindings[layer], &event.key, &modifiers)
+                    }
+                    keyboard::KeyEventType::RELEASED => Vec::new(),
+                };
 
-        for device in api.device_list() {
-            if device.vendor_id() == self.vid && device.product_id() == self.pid {
-                return true;
+                for gesture in triggers.event(&event, &modifiers, &found, now) {
+                    self.dispatch(&bindings, &gesture, &mut layers);
+                }
+            }
+
+            for key in released {
+                held_modifiers.remove(&key);
+            }
+
+            for gesture in triggers.tick(now) {
+                self.dispatch(&bindings, &gesture, &mut layers);
             }
         }
 
-        return false;
+        Ok(())
     }
 
-    pub fn process_events(&self, hid_device: HidDevice, stop: &Arc<AtomicBool>) -> Result<(), HidError> {
-        let mut keyboard = keyboard::Keyboard::new();
+    // Runs bindings of the layer which resolved the key on its press
+    fn dispatch(&self, bindings: &[MacrosBinding], gesture: &Gesture, layers: &mut LayerStack) {
+        let bindings = &bindings[layers.layer_of(&gesture.key)];
 
-        while !stop.load(Ordering::Relaxed) {
-            let mut buf = [0u8; 18];
-            let res = match hid_device.read_timeout(&mut buf[..], 2500)  {
-                Ok(r) => r,
-                Err(e) => {
-                    println!("Error while reading from device: {}", e);
-                    return Err(e);
-                }
-            };
+        for binding in binding::find(bindings, &gesture.key, &gesture.modifiers) {
+            match &binding.action {
+                Action::Macro(action) => {
+                    let event_type = if action.is_passthrough() {
+                        match gesture.kind {
+                            GestureType::Press => keyboard::KeyEventType::PRESSED,
+                            GestureType::Up => keyboard::KeyEventType::RELEASED,
+                            _ => continue,
+                        }
+                    } else if gesture.kind.matches(&binding.trigger) {
+                        gesture.kind.event_type()
+                    } else {
+                        continue;
+                    };
 
-            if res == 0 {
-                continue;
+                    action.run(event_type);
+                }
+                Action::Layer(action) => {
+                    layers.apply(action, gesture.kind, gesture.kind.matches(&binding.trigger))
+                }
+                Action::Panic(executor) => {
+                    if gesture.kind.matches(&binding.trigger) {
+                        executor.cancel_all();
+                    }
+                }
             }
+        }
+    }
+}
 
-            for event in keyboard.events(&buf, res) {
-                if event.event_type == keyboard::KeyEventType::PRESSED {
-                    continue;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binding::{Binding, Chord, Trigger};
+    use crate::config::{ModifiersMatch, Overlap};
+    use crate::executor::{CancelToken, Executor, MacroRunner};
+    use crate::input::DeviceInfo;
+    use crate::keyboard::{Key, KeyEvent, KeyEventType};
+    use crate::macros::Macro;
+    use crate::output::recording::RecordingOutput;
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::thread;
+
+    type Batches = Arc<Mutex<VecDeque<Result<Vec<KeyEvent>, InputError>>>>;
+
+    // Backend replaying batches of key events, stops the device once they run out.
+    // Every opened reader continues with batches left by the previous one.
+    struct FakeInput {
+        batches: Batches,
+        stop: StopFlag,
+    }
+
+    struct FakeReader {
+        batches: Batches,
+        stop: StopFlag,
+    }
+
+    fn fake_devices() -> Vec<DeviceInfo> {
+        vec![DeviceInfo {
+            vid: 1,
+            pid: 2,
+            name: "fake".to_string(),
+            serial: None,
+            interface_number: None,
+            usage_page: None,
+            usage: None,
+            path: "fake".to_string(),
+        }]
+    }
+
+    impl InputBackend for FakeInput {
+        fn enumerate(&mut self) -> Vec<DeviceInfo> {
+            fake_devices()
+        }
+
+        fn open(&mut self, _selector: &DeviceSelector) -> Result<Box<dyn InputReader>, InputError> {
+            Ok(Box::new(FakeReader {
+                batches: self.batches.clone(),
+                stop: self.stop.clone(),
+            }))
+        }
+    }
+
+    impl InputReader for FakeReader {
+        fn read_timeout(&mut self, _timeout: Duration) -> Result<Vec<KeyEvent>, InputError> {
+            match self.batches.lock().unwrap().pop_front() {
+                Some(batch) => batch,
+                None => {
+                    self.stop.stop();
+                    Ok(Vec::new())
                 }
+            }
+        }
+    }
+
+    struct Record {
+        events: Sender<KeyEventType>,
+    }
+
+    impl Macro for Record {
+        fn execute(&self, event_type: &KeyEventType, _cancel: &CancelToken) {
+            self.events.send(*event_type).unwrap();
+        }
+    }
+
+    fn key(key: Key, event_type: KeyEventType) -> KeyEvent {
+        KeyEvent { key, event_type }
+    }
+
+    // Record macro on release of A
+    fn record_bindings() -> (MacrosBinding, Receiver<KeyEventType>) {
+        record_chord(BTreeSet::new())
+    }
+
+    // Record macro on release of A held with the modifiers
+    fn record_chord(modifiers: BTreeSet<Key>) -> (MacrosBinding, Receiver<KeyEventType>) {
+        let (sender, receiver) = mpsc::channel();
+        let mut bindings = MacrosBinding::new();
+        bindings.insert(
+            Chord {
+                modifiers,
+                key: Key::A,
+            },
+            vec![Binding {
+                matching: ModifiersMatch::Exact,
+                trigger: Trigger::Release,
+                action: Action::Macro(MacroRunner::new(
+                    Box::new(Record { events: sender }),
+                    Overlap::Queue,
+                    &Executor::new(Arc::new(RecordingOutput::default())),
+                )),
+            }],
+        );
+        (bindings, receiver)
+    }
+
+    fn new_device(input: Box<dyn InputBackend>, bindings: MacrosBinding) -> Device {
+        let selector = DeviceSelector {
+            vid: 1,
+            pid: 2,
+            serial: None,
+            interface_number: None,
+            usage_page: None,
+            usage: None,
+            path: None,
+        };
+        let reconnect = Reconnect {
+            max_retries: Some(1),
+            delay_ms: Some(0),
+            max_delay_ms: None,
+        };
+        Device::new(
+            selector,
+            input,
+            Arc::new(Hotplug::default()),
+            reconnect,
+            vec![bindings],
+        )
+    }
+
+    // Device running Record macro on release of A
+    fn device(
+        batches: Vec<Result<Vec<KeyEvent>, InputError>>,
+    ) -> (Device, StopFlag, Receiver<KeyEventType>) {
+        let (bindings, receiver) = record_bindings();
+        let input = FakeInput {
+            batches: Arc::new(Mutex::new(batches.into())),
+            stop: StopFlag::new(),
+        };
+        let stop = input.stop.clone();
+
+        (new_device(Box::new(input), bindings), stop, receiver)
+    }
 
-                match self.macros.get(&event.key) {
-                    Some(m) => m.execute(),
-                    None => {}
+    #[test]
+    fn runs_macros_for_fake_input() {
+        let (mut device, stop, receiver) = device(vec![
+            Ok(vec![key(Key::A, KeyEventType::PRESSED)]),
+            Ok(vec![key(Key::A, KeyEventType::RELEASED)]),
+            // Chord with a modifier is not bound
+            Ok(vec![
+                key(Key::ControlLeft, KeyEventType::PRESSED),
+                key(Key::A, KeyEventType::PRESSED),
+            ]),
+            Ok(vec![
+                key(Key::A, KeyEventType::RELEASED),
+                key(Key::ControlLeft, KeyEventType::RELEASED),
+            ]),
+        ]);
+        assert_eq!(device.listen(stop), Ok(()));
+
+        let timeout = Duration::from_secs(1);
+        assert_eq!(receiver.recv_timeout(timeout), Ok(KeyEventType::RELEASED));
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn releases_modifier_after_keys_of_the_report() {
+        let (bindings, receiver) = record_chord(BTreeSet::from([Key::ControlLeft]));
+        let input = FakeInput {
+            batches: Arc::new(Mutex::new(VecDeque::from([
+                Ok(vec![
+                    key(Key::ControlLeft, KeyEventType::PRESSED),
+                    key(Key::A, KeyEventType::PRESSED),
+                ]),
+                // Decoders report modifiers first, A is still released with Ctrl held
+                Ok(vec![
+                    key(Key::ControlLeft, KeyEventType::RELEASED),
+                    key(Key::A, KeyEventType::RELEASED),
+                ]),
+                // Same for a key pressed in the report releasing Ctrl
+                Ok(vec![key(Key::ControlLeft, KeyEventType::PRESSED)]),
+                Ok(vec![
+                    key(Key::ControlLeft, KeyEventType::RELEASED),
+                    key(Key::A, KeyEventType::PRESSED),
+                ]),
+                Ok(vec![key(Key::A, KeyEventType::RELEASED)]),
+            ]))),
+            stop: StopFlag::new(),
+        };
+        let stop = input.stop.clone();
+        let mut device = new_device(Box::new(input), bindings);
+        assert_eq!(device.listen(stop), Ok(()));
+
+        let timeout = Duration::from_secs(1);
+        assert_eq!(receiver.recv_timeout(timeout), Ok(KeyEventType::RELEASED));
+        assert_eq!(receiver.recv_timeout(timeout), Ok(KeyEventType::RELEASED));
+    }
+
+    #[test]
+    fn reopens_device_after_errors() {
+        let glitch = || Err(InputError::Other("glitch".to_string()));
+        let (mut device, stop, receiver) = device(vec![
+            glitch(),
+            Ok(vec![key(Key::A, KeyEventType::PRESSED)]),
+            Ok(vec![key(Key::A, KeyEventType::RELEASED)]),
+            // Successful read resets retries, second error is retried again
+            glitch(),
+            Ok(vec![key(Key::A, KeyEventType::PRESSED)]),
+            Ok(vec![key(Key::A, KeyEventType::RELEASED)]),
+            // Retries are used up by consecutive errors
+            glitch(),
+            glitch(),
+            Ok(vec![key(Key::A, KeyEventType::PRESSED)]),
+            Ok(vec![key(Key::A, KeyEventType::RELEASED)]),
+        ]);
+        let error = device.listen(stop.clone()).unwrap_err();
+        assert_eq!(error.exit_code(), crate::error::EXIT_DEVICE);
+        assert!(!stop.is_stopped());
+
+        let timeout = Duration::from_secs(1);
+        assert_eq!(receiver.recv_timeout(timeout), Ok(KeyEventType::RELEASED));
+        assert_eq!(receiver.recv_timeout(timeout), Ok(KeyEventType::RELEASED));
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    // Backend whose device is missing until it was looked for a few times
+    struct LateInput {
+        absent: usize,
+        hotplug: Arc<Hotplug>,
+        batches: Batches,
+        stop: StopFlag,
+    }
+
+    impl InputBackend for LateInput {
+        fn enumerate(&mut self) -> Vec<DeviceInfo> {
+            if self.absent == 0 {
+                return fake_devices();
+            }
+            self.absent -= 1;
+            // Device workers wait for the next change
+            self.hotplug.notify();
+            Vec::new()
+        }
+
+        fn open(&mut self, _selector: &DeviceSelector) -> Result<Box<dyn InputReader>, InputError> {
+            Ok(Box::new(FakeReader {
+                batches: self.batches.clone(),
+                stop: self.stop.clone(),
+            }))
+        }
+    }
+
+    #[test]
+    fn waits_for_missing_device() {
+        let (bindings, receiver) = record_bindings();
+        let hotplug = Arc::new(Hotplug::default());
+        let input = LateInput {
+            // Longer than retries of the device allow
+            absent: 3,
+            hotplug: hotplug.clone(),
+            batches: Arc::new(Mutex::new(VecDeque::from([
+                Ok(vec![key(Key::A, KeyEventType::PRESSED)]),
+                Ok(vec![key(Key::A, KeyEventType::RELEASED)]),
+            ]))),
+            stop: StopFlag::new(),
+        };
+        let stop = input.stop.clone();
+        let mut device = new_device(Box::new(input), bindings);
+        device.hotplug = hotplug;
+
+        assert_eq!(device.listen(stop), Ok(()));
+        let timeout = Duration::from_secs(1);
+        assert_eq!(receiver.recv_timeout(timeout), Ok(KeyEventType::RELEASED));
+    }
+
+    // Backend whose device is listed but cannot be opened
+    struct DisconnectedInput;
+
+    impl InputBackend for DisconnectedInput {
+        fn enumerate(&mut self) -> Vec<DeviceInfo> {
+            fake_devices()
+        }
+
+        fn open(&mut self, _selector: &DeviceSelector) -> Result<Box<dyn InputReader>, InputError> {
+            Err(InputError::Disconnected("fake".to_string()))
+        }
+    }
+
+    #[test]
+    fn gives_up_on_device_failing_to_open() {
+        let (bindings, _) = record_bindings();
+        let mut device = new_device(Box::new(DisconnectedInput), bindings);
+
+        let error = device.listen(StopFlag::new()).unwrap_err();
+        assert_eq!(error.exit_code(), crate::error::EXIT_DEVICE_MISSING);
+    }
+
+    #[test]
+    fn gives_up_on_permission_error() {
+        let (mut device, stop, receiver) = device(vec![
+            Err(InputError::PermissionDenied("/dev/hidraw0".to_string())),
+            Ok(vec![key(Key::A, KeyEventType::PRESSED)]),
+            Ok(vec![key(Key::A, KeyEventType::RELEASED)]),
+        ]);
+        let error = device.listen(stop.clone()).unwrap_err();
+
+        assert_eq!(error.exit_code(), crate::error::EXIT_PERMISSION);
+        assert!(!stop.is_stopped());
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    // Backend delivering batches sent by the test, the device stops once the sender is dropped
+    struct ChannelInput {
+        batches: Arc<Mutex<Receiver<Vec<KeyEvent>>>>,
+        stop: StopFlag,
+        opened: Arc<AtomicUsize>,
+    }
+
+    struct ChannelReader {
+        batches: Arc<Mutex<Receiver<Vec<KeyEvent>>>>,
+        stop: StopFlag,
+    }
+
+    impl InputBackend for ChannelInput {
+        fn enumerate(&mut self) -> Vec<DeviceInfo> {
+            fake_devices()
+        }
+
+        fn open(&mut self, _selector: &DeviceSelector) -> Result<Box<dyn InputReader>, InputError> {
+            self.opened.fetch_add(1, Ordering::Relaxed);
+            Ok(Box::new(ChannelReader {
+                batches: self.batches.clone(),
+                stop: self.stop.clone(),
+            }))
+        }
+    }
+
+    impl InputReader for ChannelReader {
+        fn read_timeout(&mut self, timeout: Duration) -> Result<Vec<KeyEvent>, InputError> {
+            match self.batches.lock().unwrap().recv_timeout(timeout) {
+                Ok(batch) => Ok(batch),
+                Err(mpsc::RecvTimeoutError::Timeout) => Ok(Vec::new()),
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    self.stop.stop();
+                    Ok(Vec::new())
                 }
             }
         }
+    }
 
-        Ok(())
+    #[test]
+    fn swaps_layers_while_open() {
+        let (sender, batches) = mpsc::channel();
+        let input = ChannelInput {
+            batches: Arc::new(Mutex::new(batches)),
+            stop: StopFlag::new(),
+            opened: Arc::new(AtomicUsize::new(0)),
+        };
+        let stop = input.stop.clone();
+        let opened = input.opened.clone();
+
+        let (bindings, old) = record_bindings();
+        let mut device = new_device(Box::new(input), bindings);
+        let layers = device.layers();
+        let listening = thread::spawn(move || device.listen(stop));
+
+        let tap = || {
+            vec![
+                key(Key::A, KeyEventType::PRESSED),
+                key(Key::A, KeyEventType::RELEASED),
+            ]
+        };
+        let timeout = Duration::from_secs(1);
+
+        sender.send(tap()).unwrap();
+        assert_eq!(old.recv_timeout(timeout), Ok(KeyEventType::RELEASED));
+
+        let (bindings, new) = record_bindings();
+        layers.replace(vec![bindings]);
+        sender.send(tap()).unwrap();
+        assert_eq!(new.recv_timeout(timeout), Ok(KeyEventType::RELEASED));
+        assert!(old.recv_timeout(Duration::from_millis(100)).is_err());
+
+        drop(sender);
+        assert_eq!(listening.join().unwrap(), Ok(()));
+        assert_eq!(opened.load(Ordering::Relaxed), 1);
     }
 }
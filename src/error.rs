@@ -0,0 +1,76 @@
+// Errors reported to the user, every kind of failure exits with its own code
+use std::fmt;
+
+use crate::input::InputError;
+use crate::keyboard::Key;
+
+// Process exit codes, scripts can tell what went wrong
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_CONFIG: i32 = 2;
+pub const EXIT_PERMISSION: i32 = 3;
+pub const EXIT_DEVICE_MISSING: i32 = 4;
+pub const EXIT_DEVICE: i32 = 5;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    // Configuration cannot be read or is invalid, message tells where
+    Config(String),
+    // Access denied to something other than an input device, e.g. /dev/uinput
+    Permission(String),
+    // Device was given up, after a permission error or when retries ran out
+    Device { device: String, source: InputError },
+    // Output backend cannot be created
+    Output(String),
+    // HID library cannot be initialized
+    Hid(String),
+    // Handler of termination or reload signals cannot be installed
+    Signal(String),
+    // Usage of the HID usage page has no key
+    UnknownUsage { page: u16, usage: u16 },
+    // Linux input event code has no key
+    UnknownKeycode(u16),
+    // Key cannot be sent through the output
+    UnsupportedKey { key: Key, output: &'static str },
+    // Character of a text cannot be typed through the output
+    UnsupportedChar { c: char, output: &'static str },
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) => EXIT_CONFIG,
+            Error::Permission(_) => EXIT_PERMISSION,
+            Error::Device { source, .. } => match source {
+                InputError::PermissionDenied(_) => EXIT_PERMISSION,
+                InputError::Disconnected(_) => EXIT_DEVICE_MISSING,
+                InputError::Other(_) => EXIT_DEVICE,
+            },
+            _ => EXIT_FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(e) => write!(f, "invalid configuration: {}", e),
+            Error::Permission(e) => write!(f, "permission denied: {}", e),
+            Error::Device { device, source } => write!(f, "{}: {}", device, source),
+            Error::Output(e) => write!(f, "cannot create output: {}", e),
+            Error::Hid(e) => write!(f, "cannot initialize HID library: {}", e),
+            Error::Signal(e) => write!(f, "cannot handle signals: {}", e),
+            Error::UnknownUsage { page, usage } => {
+                write!(f, "unknown usage {:#x} of usage page {:#x}", usage, page)
+            }
+            Error::UnknownKeycode(code) => write!(f, "unknown input event code {}", code),
+            Error::UnsupportedKey { key, output } => {
+                write!(f, "key {:?} is not supported by {} output", key, output)
+            }
+            Error::UnsupportedChar { c, output } => {
+                write!(f, "character {:?} cannot be typed by {} output", c, output)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,356 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Overlap;
+use crate::keyboard::{Key, KeyEventType};
+use crate::macros::{send, Macro};
+use crate::output::{OutputBackend, OutputEvent};
+use crate::stop::StopFlag;
+
+// Longest time to wait for cancelled macros on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Shared flag telling a running macro to stop at its next step.
+// It also tracks keys the macro holds down, so they are released when it is cancelled.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: StopFlag,
+    held: Arc<Mutex<Vec<Key>>>,
+}
+
+impl CancelToken {
+    // Token of the next run, keys held by earlier runs stay tracked
+    fn renew(&self) -> CancelToken {
+        CancelToken {
+            cancelled: StopFlag::new(),
+            held: self.held.clone(),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.stop();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.is_stopped()
+    }
+
+    // Sleeps for the duration unless cancelled earlier, returns false when cancelled
+    pub fn sleep(&self, duration: Duration) -> bool {
+        self.cancelled.sleep(duration)
+    }
+
+    pub fn pressed(&self, key: Key) {
+        let mut held = self.held.lock().unwrap();
+        if !held.contains(&key) {
+            held.push(key);
+        }
+    }
+
+    pub fn released(&self, key: &Key) {
+        self.held.lock().unwrap().retain(|k| k != key);
+    }
+
+    // Releases held keys, the last pressed first
+    fn release_held(&self, output: &dyn OutputBackend) {
+        let keys = std::mem::take(&mut *self.held.lock().unwrap());
+        for key in keys.into_iter().rev() {
+            send(output, &OutputEvent::KeyUp(key), self);
+        }
+    }
+}
+
+#[derive(Default)]
+struct RunState {
+    running: usize,
+    pending: VecDeque<KeyEventType>,
+    cancel: CancelToken,
+}
+
+impl RunState {
+    // Cancels runs in progress and forgets pending ones
+    fn cancel(&mut self) {
+        self.cancel.cancel();
+        self.cancel = self.cancel.renew();
+        self.pending.clear();
+    }
+}
+
+// Keeps track of all macro runners, so in-flight macros can be cancelled at once.
+// State of a dropped runner lives on only while its last run is in progress.
+pub struct Executor {
+    runners: Mutex<Vec<Weak<Mutex<RunState>>>>,
+    // Threads running macros, notified when one of them ends
+    running: Arc<(Mutex<usize>, Condvar)>,
+    output: Arc<dyn OutputBackend>,
+}
+
+impl Executor {
+    pub fn new(output: Arc<dyn OutputBackend>) -> Executor {
+        Executor {
+            runners: Mutex::default(),
+            running: Arc::default(),
+            output,
+        }
+    }
+
+    // Cancels all macros and releases keys they hold down
+    pub fn cancel_all(&self) {
+        let runners = self.runners.lock().unwrap();
+        for state in runners.iter().filter_map(Weak::upgrade) {
+            let mut state = state.lock().unwrap();
+            if state.running > 0 {
+                // Keys are released by the run once it stops
+                state.cancel();
+            } else {
+                state.cancel.release_held(self.output.as_ref());
+            }
+        }
+    }
+
+    // Cancels all macros and waits for them to stop
+    pub fn shutdown(&self) {
+        self.cancel_all();
+
+        let (running, ended) = &*self.running;
+        let running = running.lock().unwrap();
+        let (_running, result) = ended
+            .wait_timeout_while(running, SHUTDOWN_TIMEOUT, |running| *running > 0)
+            .unwrap();
+        if result.timed_out() {
+            println!("Some macros did not stop in time");
+        }
+    }
+}
+
+// Runs the macro of a binding outside of the device read loop.
+// Runs triggered while the macro is busy are handled by the overlap policy.
+pub struct MacroRunner {
+    action: Arc<dyn Macro + Send>,
+    overlap: Overlap,
+    state: Arc<Mutex<RunState>>,
+    running: Arc<(Mutex<usize>, Condvar)>,
+    output: Arc<dyn OutputBackend>,
+}
+
+impl MacroRunner {
+    pub fn new(
+        action: Box<dyn Macro + Send>,
+        overlap: Overlap,
+        executor: &Executor,
+    ) -> MacroRunner {
+        // Press and release of passthrough keys must not be reordered or lost
+        let overlap = if action.is_passthrough() {
+            Overlap::Queue
+        } else {
+            overlap
+        };
+
+        let state = Arc::new(Mutex::new(RunState::default()));
+        let mut runners = executor.runners.lock().unwrap();
+        // Runners of replaced configurations are gone
+        runners.retain(|r| r.strong_count() > 0);
+        runners.push(Arc::downgrade(&state));
+        drop(runners);
+
+        MacroRunner {
+            action: Arc::from(action),
+            overlap,
+            state,
+            running: executor.running.clone(),
+            output: executor.output.clone(),
+        }
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.action.is_passthrough()
+    }
+
+    pub fn run(&self, event_type: KeyEventType) {
+        let mut state = self.state.lock().unwrap();
+        if state.running > 0 {
+            match self.overlap {
+                Overlap::Queue => state.pending.push_back(event_type),
+                Overlap::Restart => {
+                    state.cancel();
+                    state.pending.push_back(event_type);
+                }
+                Overlap::Cancel => state.cancel(),
+                Overlap::Parallel => self.spawn(&mut state, event_type),
+                Overlap::Drop => {}
+            }
+            return;
+        }
+
+        self.spawn(&mut state, event_type);
+    }
+
+    // Starts a thread running the event, sequential runners continue with pending events
+    fn spawn(&self, state: &mut RunState, event_type: KeyEventType) {
+        state.running += 1;
+
+        let action = self.action.clone();
+        let shared = self.state.clone();
+        let output = self.output.clone();
+        let running = self.running.clone();
+        let sequential = self.overlap != Overlap::Parallel;
+        let mut next = Some((event_type, state.cancel.clone()));
+        *running.0.lock().unwrap() += 1;
+
+        thread::spawn(move || {
+            while let Some((event_type, cancel)) = next {
+                action.execute(&event_type, &cancel);
+
+                let mut state = shared.lock().unwrap();
+                if cancel.is_cancelled() {
+                    cancel.release_held(output.as_ref());
+                }
+                next = match state.pending.pop_front() {
+                    Some(event_type) if sequential => Some((event_type, state.cancel.clone())),
+                    _ => {
+                        state.running -= 1;
+                        None
+                    }
+                };
+            }
+
+            let (count, ended) = &*running;
+            *count.lock().unwrap() -= 1;
+            ended.notify_all();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::recording::RecordingOutput;
+    use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+
+    // Longest wait for a macro, exceeding it fails the test instead of hanging it
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    // Reports start of every run and waits for the test to let it finish.
+    // Finish is reported only when the run was not cancelled meanwhile.
+    struct Slow {
+        events: Sender<&'static str>,
+        proceed: Mutex<Receiver<()>>,
+    }
+
+    impl Macro for Slow {
+        fn execute(&self, _event_type: &KeyEventType, cancel: &CancelToken) {
+            self.events.send("start").unwrap();
+            let proceed = self.proceed.lock().unwrap().recv();
+            if proceed.is_ok() && !cancel.is_cancelled() {
+                self.events.send("finish").unwrap();
+            }
+        }
+    }
+
+    // Reports start of its run and waits until it is cancelled
+    struct Endless {
+        events: Sender<&'static str>,
+    }
+
+    impl Macro for Endless {
+        fn execute(&self, _event_type: &KeyEventType, cancel: &CancelToken) {
+            self.events.send("start").unwrap();
+            if cancel.sleep(TIMEOUT) {
+                self.events.send("finish").unwrap();
+            }
+        }
+    }
+
+    fn slow(
+        overlap: Overlap,
+        executor: &Executor,
+    ) -> (MacroRunner, Receiver<&'static str>, Sender<()>) {
+        let (events, receiver) = mpsc::channel();
+        let (proceed, proceed_receiver) = mpsc::channel();
+        let action = Slow {
+            events,
+            proceed: Mutex::new(proceed_receiver),
+        };
+        let runner = MacroRunner::new(Box::new(action), overlap, executor);
+        (runner, receiver, proceed)
+    }
+
+    // Events until all runs are over and the macro is dropped
+    fn collect(receiver: Receiver<&'static str>) -> Vec<&'static str> {
+        let mut events = Vec::new();
+        loop {
+            match receiver.recv_timeout(TIMEOUT) {
+                Ok(event) => events.push(event),
+                Err(RecvTimeoutError::Disconnected) => return events,
+                Err(RecvTimeoutError::Timeout) => panic!("macro did not finish: {:?}", events),
+            }
+        }
+    }
+
+    // Triggers the macro a second time while the first run is in progress,
+    // runs are let go on once the given number of them started
+    fn run_twice(overlap: Overlap, started: usize) -> Vec<&'static str> {
+        let executor = Executor::new(Arc::new(RecordingOutput::default()));
+        let (runner, receiver, proceed) = slow(overlap, &executor);
+
+        runner.run(KeyEventType::RELEASED);
+        let mut events = vec![receiver.recv_timeout(TIMEOUT).unwrap()];
+        runner.run(KeyEventType::RELEASED);
+        drop(runner);
+
+        while events.len() < started {
+            events.push(receiver.recv_timeout(TIMEOUT).unwrap());
+        }
+        // Cancelled runs may be over and the macro dropped already
+        let _ = proceed.send(());
+        let _ = proceed.send(());
+
+        events.extend(collect(receiver));
+        events
+    }
+
+    #[test]
+    fn overlapping_runs() {
+        assert_eq!(
+            run_twice(Overlap::Queue, 1),
+            ["start", "finish", "start", "finish"]
+        );
+        assert_eq!(run_twice(Overlap::Drop, 1), ["start", "finish"]);
+        // Cancelled run ends once let go, only then the next one starts
+        assert_eq!(run_twice(Overlap::Restart, 1), ["start", "start", "finish"]);
+        assert_eq!(run_twice(Overlap::Cancel, 1), ["start"]);
+        assert_eq!(
+            run_twice(Overlap::Parallel, 2),
+            ["start", "start", "finish", "finish"]
+        );
+    }
+
+    #[test]
+    fn shutdown_cancels_running_macros() {
+        let executor = Executor::new(Arc::new(RecordingOutput::default()));
+        let (events, receiver) = mpsc::channel();
+        let action = Endless { events };
+        let runner = MacroRunner::new(Box::new(action), Overlap::Queue, &executor);
+
+        runner.run(KeyEventType::RELEASED);
+        runner.run(KeyEventType::RELEASED);
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok("start"));
+        executor.shutdown();
+
+        assert_eq!(runner.state.lock().unwrap().running, 0);
+        drop(runner);
+        assert!(collect(receiver).is_empty());
+    }
+
+    #[test]
+    fn forgets_dropped_runners() {
+        let executor = Executor::new(Arc::new(RecordingOutput::default()));
+        for _ in 0..3 {
+            slow(Overlap::Queue, &executor);
+        }
+        let (_runner, _, _) = slow(Overlap::Queue, &executor);
+        assert_eq!(executor.runners.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,367 @@
+// Formats of configuration files. All of them are parsed into the same tree of
+// values, so validation and merging of files do not depend on the format.
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer, IgnoredAny, Visitor};
+use serde_json::value::RawValue;
+use serde_yaml::Value;
+use toml::Spanned;
+
+use crate::check::{Problem, Segment};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl Format {
+    // Told by the file extension, YAML unless it is .toml or .json
+    pub fn of(path: &Path) -> Format {
+        let extension = path.extension().and_then(|e| e.to_str());
+        match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("toml") => Format::Toml,
+            Some("json") => Format::Json,
+            _ => Format::Yaml,
+        }
+    }
+
+    pub fn parse(self, source: &str) -> Result<Value, Problem> {
+        match self {
+            Format::Yaml => serde_yaml::from_str(source).map_err(|e| {
+                let position = e.location().map(|l| (l.line(), l.column()));
+                syntax_error(position, e.to_string())
+            }),
+            Format::Json => serde_json::from_str(source).map_err(|e| {
+                let position = Some((e.line(), e.column())).filter(|&(line, _)| line > 0);
+                syntax_error(position, e.to_string())
+            }),
+            Format::Toml => {
+                let value: toml::Value = toml::from_str(source).map_err(|e| {
+                    let position = e.line_col().map(|(line, column)| (line + 1, column + 1));
+                    syntax_error(position, e.to_string())
+                })?;
+                serde_yaml::to_value(value).map_err(|e| syntax_error(None, e.to_string()))
+            }
+        }
+    }
+
+    // Line and column of the value at the path, of its closest parent when the value
+    // is not in the source. Positions are told by the parser, so all styles are located.
+    pub fn locate(self, source: &str, path: &[Segment]) -> Option<(usize, usize)> {
+        (1..=path.len())
+            .rev()
+            .find_map(|len| self.position(source, &path[..len]))
+    }
+
+    fn position(self, source: &str, path: &[Segment]) -> Option<(usize, usize)> {
+        match self {
+            Format::Yaml => {
+                // Value at the path refuses to be deserialized, the error is marked with its position
+                let deserializer = serde_yaml::Deserializer::from_str(source);
+                let error = Seek::<Here>::new(path).deserialize(deserializer).err()?;
+                error.location().map(|l| (l.line(), l.column()))
+            }
+            Format::Json => {
+                // Raw value is a slice of the source
+                let mut deserializer = serde_json::Deserializer::from_str(source);
+                let value = Seek::<&RawValue>::new(path)
+                    .deserialize(&mut deserializer)
+                    .ok()??;
+                let start = value.get().as_ptr() as usize - source.as_ptr() as usize;
+                Some(line_column(source, start))
+            }
+            Format::Toml => {
+                let value = Seek::<Spanned<IgnoredAny>>::new(path)
+                    .deserialize(&mut toml::Deserializer::new(source));
+                let start = match value.as_ref().map(|v| v.as_ref().map(Spanned::span)) {
+                    Ok(None) => return None,
+                    Ok(Some((start, end))) if end > 0 => start,
+                    // Tables have no span, the first key in them is located instead
+                    _ => {
+                        Seek::<FirstKey>::new(path)
+                            .deserialize(&mut toml::Deserializer::new(source))
+                            .ok()??
+                            .0
+                    }
+                };
+                Some(line_column(source, start))
+            }
+        }
+    }
+}
+
+// Deserializes the value at the path, everything else is skipped
+struct Seek<'a, T> {
+    path: &'a [Segment],
+    value: PhantomData<T>,
+}
+
+impl<'a, T> Seek<'a, T> {
+    fn new(path: &'a [Segment]) -> Seek<'a, T> {
+        Seek {
+            path,
+            value: PhantomData,
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> DeserializeSeed<'de> for Seek<'_, T> {
+    type Value = Option<T>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Option<T>, D::Error> {
+        match self.path.first() {
+            None => T::deserialize(deserializer).map(Some),
+            Some(Segment::Index(_)) => deserializer.deserialize_seq(self),
+            Some(_) => deserializer.deserialize_map(self),
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for Seek<'_, T> {
+    type Value = Option<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a sequence or a mapping")
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Option<T>, A::Error> {
+        let index = match self.path[0] {
+            Segment::Index(index) => index,
+            _ => return Ok(None),
+        };
+        for _ in 0..index {
+            if seq.next_element::<IgnoredAny>()?.is_none() {
+                return Ok(None);
+            }
+        }
+        let found = seq.next_element_seed(Seek::new(&self.path[1..]))?;
+        // Parsers check that the whole sequence was read
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(found.flatten())
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Option<T>, A::Error> {
+        let mut found = None;
+        while let Some(key) = map.next_key::<Value>()? {
+            if found.is_none() && key.as_str() == self.path[0].key() {
+                found = map.next_value_seed(Seek::new(&self.path[1..]))?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(found)
+    }
+}
+
+// Value which cannot be deserialized
+struct Here;
+
+impl<'de> Deserialize<'de> for Here {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Here, D::Error> {
+        deserializer.deserialize_any(Here)
+    }
+}
+
+impl Visitor<'_> for Here {
+    type Value = Here;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "nothing")
+    }
+}
+
+// Start of the first key of a table, or of the first table of an array
+struct FirstKey(usize);
+
+impl<'de> Deserialize<'de> for FirstKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<FirstKey, D::Error> {
+        deserializer.deserialize_any(FirstKey(0))
+    }
+}
+
+impl<'de> Visitor<'de> for FirstKey {
+    type Value = FirstKey;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a table")
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<FirstKey, A::Error> {
+        let table = seq.next_element::<FirstKey>()?;
+        let table = table.ok_or_else(|| de::Error::custom("empty array"))?;
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(table)
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<FirstKey, A::Error> {
+        let key = map.next_key::<Spanned<String>>()?;
+        let key = key.ok_or_else(|| de::Error::custom("empty table"))?;
+        map.next_value::<IgnoredAny>()?;
+        while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+        Ok(FirstKey(key.start()))
+    }
+}
+
+// Line and column of the byte offset, 1-based
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Format, String> {
+        match name {
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "toml" => Ok(Format::Toml),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "unknown format {}, expected yaml, toml or json",
+                name
+            )),
+        }
+    }
+}
+
+// Parsers append positions to the message, the problem is prefixed by its position
+// already. Positions of the context, e.g. of the scalar being parsed, are dropped too.
+fn syntax_error(position: Option<(usize, usize)>, mut message: String) -> Problem {
+    const AT: &str = " at line ";
+    let mut from = 0;
+    while let Some(found) = message[from..].find(AT) {
+        let start = from + found;
+        let rest = &message[start + AT.len()..];
+        let numbers = |text: &str| text.len() - text.trim_start_matches(char::is_numeric).len();
+        let line = numbers(rest);
+        match rest[line..].strip_prefix(" column ") {
+            Some(column) if line > 0 && numbers(column) > 0 => {
+                let end = message.len() - column.len() + numbers(column);
+                message.replace_range(start..end, "");
+            }
+            _ => from = start + AT.len(),
+        }
+    }
+
+    Problem {
+        position,
+        path: String::new(),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::{self, Context};
+
+    const YAML: &str = r#"
+output: uinput
+devices:
+  - vid: 1
+    pid: 2
+    macros:
+      - key: "A"
+        shortcut: { keys: ["Wrong"] }
+"#;
+
+    const TOML: &str = r#"
+output = "uinput"
+
+[[devices]]
+vid = 1
+pid = 2
+
+[[devices.macros]]
+key = "A"
+shortcut = { keys = ["Wrong"] }
+"#;
+
+    const JSON: &str = r#"{
+  "output": "uinput",
+  "devices": [
+    { "vid": 1, "pid": 2, "macros": [{ "key": "A", "shortcut": { "keys": ["Wrong"] } }] }
+  ]
+}"#;
+
+    fn problems(source: &str, format: Format) -> Vec<String> {
+        let root = format.parse(source).unwrap();
+        match check::validate(source, format, &Context::new([&root])) {
+            Ok(_) => Vec::new(),
+            Err(problems) => problems.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn parses_formats_into_same_values() {
+        let yaml = Format::Yaml.parse(YAML).unwrap();
+        assert_eq!(Format::Toml.parse(TOML).unwrap(), yaml);
+        assert_eq!(Format::Json.parse(JSON).unwrap(), yaml);
+
+        assert_eq!(Format::of(Path::new("config.toml")), Format::Toml);
+        assert_eq!(Format::of(Path::new("config.JSON")), Format::Json);
+        assert_eq!(Format::of(Path::new("config")), Format::Yaml);
+    }
+
+    #[test]
+    fn reports_same_problems() {
+        let problem = "devices[0].macros[0].shortcut.keys[0]: unknown variant `Wrong`";
+        assert_eq!(problems(YAML, Format::Yaml), [format!("8:28: {}", problem)]);
+        assert_eq!(
+            problems(TOML, Format::Toml),
+            [format!("10:22: {}", problem)]
+        );
+        assert_eq!(problems(JSON, Format::Json), [format!("4:75: {}", problem)]);
+
+        let error = Format::Toml.parse("[devices\nvid = 1\n").unwrap_err();
+        assert_eq!(error.position, Some((1, 9)));
+        assert!(!error.message.contains(" at line "), "{}", error.message);
+    }
+
+    #[test]
+    fn parses_json_escapes() {
+        // Characters outside the BMP as escaped by most serializers
+        let json = r#"{"macros": {"smile": {"type": {"text": "x \ud83d\ude00"}}}}"#;
+        let yaml = "macros:\n  smile:\n    type: { text: \"x \u{1F600}\" }\n";
+        assert_eq!(
+            Format::Json.parse(json).unwrap(),
+            Format::Yaml.parse(yaml).unwrap()
+        );
+
+        use Segment::{Key as K, Name as N};
+        let text = [K("macros"), N("smile".to_string()), K("type"), K("text")];
+        assert_eq!(Format::Json.locate(json, &text), Some((1, 40)));
+
+        let error = Format::Json.parse("{\"a\": [1,\n 2 3]}").unwrap_err();
+        assert_eq!(error.position, Some((2, 4)));
+        assert_eq!(error.message, "expected `,` or `]`");
+
+        // YAML parser rejects them, positions of its context are dropped as well
+        let error = Format::Yaml.parse(json).unwrap_err();
+        assert!(!error.message.contains(" at line "), "{}", error.message);
+    }
+
+    #[test]
+    fn locates_tables() {
+        use Segment::{Index as I, Key as K};
+
+        // Tables have no position of their own, their first key is located
+        let macros = [K("devices"), I(0), K("macros"), I(0)];
+        assert_eq!(Format::Toml.locate(TOML, &macros), Some((9, 1)));
+        assert_eq!(Format::Toml.locate(TOML, &[K("devices")]), Some((5, 1)));
+        assert_eq!(Format::Json.locate(JSON, &macros), Some((4, 38)));
+
+        let shortcut = [K("devices"), I(0), K("macros"), I(0), K("shortcut")];
+        assert_eq!(Format::Toml.locate(TOML, &shortcut), Some((10, 12)));
+        assert_eq!(Format::Toml.locate(TOML, &[K("output")]), Some((2, 10)));
+    }
+}
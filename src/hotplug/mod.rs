@@ -0,0 +1,118 @@
+// Notifies device workers when devices are plugged in or removed.
+// Without a udev monitor workers fall back to polling.
+#[cfg(target_os = "linux")]
+mod udev;
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Condvar, Mutex,
+};
+use std::time::{Duration, Instant};
+
+use crate::stop::StopFlag;
+
+// How often devices are looked for when hotplug events are not available
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+pub struct Hotplug {
+    // Number of hotplug events seen so far
+    changes: Mutex<u64>,
+    changed: Condvar,
+    monitored: AtomicBool,
+}
+
+impl Hotplug {
+    // Starts listening to udev events, polling is used when it is not possible
+    pub fn start() -> Arc<Hotplug> {
+        let hotplug = Arc::new(Hotplug::default());
+
+        #[cfg(target_os = "linux")]
+        match udev::Monitor::open() {
+            Ok(monitor) => {
+                hotplug.monitored.store(true, Ordering::Relaxed);
+                let notified = hotplug.clone();
+                std::thread::spawn(move || {
+                    monitor.run(|| notified.notify());
+                    // Wake up waiting workers, so they start polling
+                    notified.monitored.store(false, Ordering::Relaxed);
+                    notified.notify();
+                });
+            }
+            Err(e) => println!("Cannot monitor udev events ({}), polling for devices", e),
+        }
+
+        hotplug
+    }
+
+    // Current change counter, to be passed to wait
+    pub fn changes(&self) -> u64 {
+        *self.changes.lock().unwrap()
+    }
+
+    pub fn notify(&self) {
+        *self.changes.lock().unwrap() += 1;
+        self.changed.notify_all();
+    }
+
+    // Wakes up waiting workers without a change, so they look at their stop flags
+    pub fn wake(&self) {
+        let _changes = self.changes.lock().unwrap();
+        self.changed.notify_all();
+    }
+
+    // Waits until a device is added or removed after the counter was read,
+    // or until the next poll when devices are not monitored.
+    // Whoever sets the stop flag has to wake the waiting workers.
+    pub fn wait(&self, seen: u64, stop: &StopFlag) {
+        let deadline = Instant::now() + POLL_INTERVAL;
+        let mut changes = self.changes.lock().unwrap();
+
+        while *changes == seen && !stop.is_stopped() {
+            if self.monitored.load(Ordering::Relaxed) {
+                changes = self.changed.wait(changes).unwrap();
+            } else {
+                let now = Instant::now();
+                if now >= deadline {
+                    return;
+                }
+                changes = self
+                    .changed
+                    .wait_timeout(changes, deadline - now)
+                    .unwrap()
+                    .0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // Monitored hotplug waits without timeout, returning at all means it was woken up
+    #[test]
+    fn wakes_up_on_change_and_stop() {
+        let hotplug = Arc::new(Hotplug::default());
+        hotplug.monitored.store(true, Ordering::Relaxed);
+        let stop = StopFlag::new();
+
+        let seen = hotplug.changes();
+        let notifier = hotplug.clone();
+        thread::spawn(move || notifier.notify());
+        hotplug.wait(seen, &stop);
+        assert_eq!(hotplug.changes(), seen + 1);
+
+        // Changes since the counter was read are not missed
+        hotplug.wait(seen, &stop);
+
+        let seen = hotplug.changes();
+        let (waiting, waiting_stop) = (hotplug.clone(), stop.clone());
+        let worker = thread::spawn(move || waiting.wait(seen, &waiting_stop));
+        stop.stop();
+        hotplug.wake();
+        worker.join().unwrap();
+        assert_eq!(hotplug.changes(), seen);
+    }
+}
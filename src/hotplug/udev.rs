@@ -0,0 +1,155 @@
+// Device events broadcast by udev over netlink, sent once udev rules
+// (device node permissions, symlinks) have been applied.
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+// Multicast group of events processed by udev, 1 would be raw kernel events
+const UDEV_EVENTS: u32 = 2;
+const UDEV_PREFIX: &[u8] = b"libudev\0";
+// Subsystems of devices read by input backends
+const SUBSYSTEMS: [&str; 2] = ["hidraw", "input"];
+
+pub struct Monitor {
+    socket: OwnedFd,
+}
+
+#[derive(Debug, PartialEq)]
+struct Uevent<'a> {
+    action: &'a str,
+    subsystem: &'a str,
+}
+
+impl Monitor {
+    pub fn open() -> io::Result<Monitor> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                libc::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = UDEV_EVENTS;
+        let res = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Monitor { socket })
+    }
+
+    // Calls notify for every added or removed input device, returns when the socket fails
+    pub fn run(&self, notify: impl Fn()) {
+        let mut buf = [0u8; 8192];
+
+        loop {
+            let res = unsafe {
+                libc::recv(
+                    self.socket.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                )
+            };
+            if res < 0 {
+                let e = io::Error::last_os_error();
+                match e.kind() {
+                    io::ErrorKind::Interrupted => continue,
+                    _ => {
+                        println!("Cannot read udev events ({}), polling for devices", e);
+                        return;
+                    }
+                }
+            }
+
+            match parse(&buf[..res as usize]) {
+                Some(event)
+                    if SUBSYSTEMS.contains(&event.subsystem)
+                        && matches!(event.action, "add" | "remove") =>
+                {
+                    notify()
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// Parses udev message, kernel messages ("action@devpath" header) are accepted as well
+fn parse(message: &[u8]) -> Option<Uevent<'_>> {
+    let properties = if message.starts_with(UDEV_PREFIX) {
+        // Prefix and magic are followed by header size, properties offset and length
+        let field = |at: usize| -> Option<usize> {
+            let bytes = message.get(at..at + 4)?;
+            Some(u32::from_ne_bytes(bytes.try_into().ok()?) as usize)
+        };
+        let (offset, length) = (field(16)?, field(20)?);
+        message.get(offset..offset.checked_add(length)?)?
+    } else {
+        let header_end = message.iter().position(|b| *b == 0)?;
+        &message[header_end + 1..]
+    };
+
+    let mut action = None;
+    let mut subsystem = None;
+    for property in properties.split(|b| *b == 0) {
+        let property = match std::str::from_utf8(property) {
+            Ok(p) => p,
+            Err(_) => continue,
+        };
+        match property.split_once('=') {
+            Some(("ACTION", value)) => action = Some(value),
+            Some(("SUBSYSTEM", value)) => subsystem = Some(value),
+            _ => {}
+        }
+    }
+
+    Some(Uevent {
+        action: action?,
+        subsystem: subsystem?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_udev_and_kernel_messages() {
+        let properties = b"ACTION=add\0DEVNAME=/dev/hidraw3\0SUBSYSTEM=hidraw\0";
+        let mut message = UDEV_PREFIX.to_vec();
+        for field in [0xfeedcafe_u32.to_be(), 40, 40, properties.len() as u32] {
+            message.extend(field.to_ne_bytes());
+        }
+        message.resize(40, 0);
+        message.extend(properties);
+
+        let expected = Uevent {
+            action: "add",
+            subsystem: "hidraw",
+        };
+        assert_eq!(parse(&message), Some(expected));
+
+        let kernel = b"remove@/devices/virtual/input/input7\0ACTION=remove\0SUBSYSTEM=input\0";
+        let expected = Uevent {
+            action: "remove",
+            subsystem: "input",
+        };
+        assert_eq!(parse(kernel), Some(expected));
+
+        assert_eq!(parse(b"libudev\0"), None);
+        assert_eq!(parse(b"add@/devices/x\0SEQNUM=1\0"), None);
+    }
+}
@@ -0,0 +1,442 @@
+// Configuration split into several files. Included files are read depth first and
+// merged in the order they were read, each including file after its includes, so
+// later definitions win: named macros are replaced and device entries with the same
+// selectors are merged binding by binding.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use serde_yaml::Value;
+
+use crate::check::{Problem, Segment};
+use crate::config::{Config, DeviceConfiguration, Macro, NamedMacro, OneOfMacros};
+use crate::error::Error;
+use crate::format::Format;
+use crate::input::DeviceSelector;
+
+pub struct File {
+    // Include joined to the including file's directory, so relative to the
+    // working directory unless absolute
+    pub path: PathBuf,
+    pub source: String,
+    pub format: Format,
+    // None when the file cannot be parsed, validation reports it
+    pub value: Option<Value>,
+}
+
+impl File {
+    fn problem(&self, path: &[Segment], message: String) -> String {
+        Problem::at(&self.source, self.format, path, message).in_file(&self.path)
+    }
+}
+
+struct Reader {
+    files: Vec<File>,
+    problems: Vec<String>,
+    // Files being read, each one included by the previous one
+    stack: Vec<PathBuf>,
+    seen: HashSet<PathBuf>,
+}
+
+// Reads the file and all files it includes, included ones first. Format of included
+// files is told by their extension. Problems with included files are returned next
+// to the files which could be read.
+pub fn read(path: &Path, format: Format) -> Result<(Vec<File>, Vec<String>), Error> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| Error::Config(format!("cannot read {}: {}", path.display(), e)))?;
+
+    let mut reader = Reader {
+        files: Vec::new(),
+        problems: Vec::new(),
+        stack: Vec::new(),
+        seen: HashSet::new(),
+    };
+    reader.add(path.to_path_buf(), source, format);
+
+    Ok((reader.files, reader.problems))
+}
+
+impl Reader {
+    fn add(&mut self, path: PathBuf, source: String, format: Format) {
+        let id = identity(&path);
+        self.seen.insert(id.clone());
+        self.stack.push(id);
+
+        let file = File {
+            value: format.parse(&source).ok(),
+            path,
+            source,
+            format,
+        };
+        let dir = file.path.parent().unwrap_or_else(|| Path::new(""));
+
+        // Files merged into this one, relative to it
+        let includes = match file.value.as_ref().and_then(|v| v.get("include")) {
+            Some(Value::Sequence(includes)) => includes.as_slice(),
+            Some(_) => {
+                let at = [Segment::Key("include")];
+                let problem = file.problem(&at, "expected a list of files".to_string());
+                self.problems.push(problem);
+                &[]
+            }
+            None => &[],
+        };
+        for (i, include) in includes.iter().enumerate() {
+            let at = [Segment::Key("include"), Segment::Index(i)];
+            let included = match include.as_str() {
+                Some(include) => dir.join(include),
+                None => {
+                    let problem = file.problem(&at, "expected a file name".to_string());
+                    self.problems.push(problem);
+                    continue;
+                }
+            };
+
+            let id = identity(&included);
+            if let Some(start) = self.stack.iter().position(|p| *p == id) {
+                let cycle: Vec<String> = self.stack[start..]
+                    .iter()
+                    .chain([&id])
+                    .map(|p| p.display().to_string())
+                    .collect();
+                let problem = file.problem(&at, format!("include cycle {}", cycle.join(" -> ")));
+                self.problems.push(problem);
+                continue;
+            }
+            // Included by another file already
+            if self.seen.contains(&id) {
+                continue;
+            }
+
+            match fs::read_to_string(&included) {
+                Ok(source) => {
+                    let format = Format::of(&included);
+                    self.add(included, source, format)
+                }
+                Err(e) => {
+                    let message = format!("cannot read {}: {}", included.display(), e);
+                    self.problems.push(file.problem(&at, message));
+                }
+            }
+        }
+
+        self.stack.pop();
+        self.files.push(file);
+    }
+}
+
+// Same file reached through different paths is read once
+fn identity(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Merges configurations of the files, given in the same order, and replaces
+// references to named macros by their definitions
+pub fn merge(files: &[File], configs: Vec<Config>) -> Result<Config, Vec<String>> {
+    let mut merged = Config {
+        output: None,
+        macros: HashMap::new(),
+        devices: Vec::new(),
+        files: files.iter().map(|f| f.path.clone()).collect(),
+    };
+    // File of the definition in use, problems of the macro point to it
+    let mut defined_in = HashMap::new();
+
+    for (f, config) in configs.into_iter().enumerate() {
+        if config.output.is_some() {
+            merged.output = config.output;
+        }
+        for (name, definition) in config.macros {
+            defined_in.insert(name.clone(), f);
+            merged.macros.insert(name, definition);
+        }
+        for device in config.devices {
+            let selector = DeviceSelector::new(&device);
+            match merged
+                .devices
+                .iter_mut()
+                .find(|d| DeviceSelector::new(d) == selector)
+            {
+                Some(earlier) => merge_device(earlier, device),
+                None => merged.devices.push(device),
+            }
+        }
+    }
+
+    let mut problems = Vec::new();
+    let mut resolved = HashMap::new();
+    for name in merged.macros.keys() {
+        match definition(&merged.macros, name) {
+            Ok(definition) => {
+                resolved.insert(name.clone(), definition);
+            }
+            Err(message) => {
+                let at = [Segment::Key("macros"), Segment::Name(name.clone())];
+                problems.push(files[defined_in[name]].problem(&at, message));
+            }
+        }
+    }
+    if !problems.is_empty() {
+        problems.sort();
+        return Err(problems);
+    }
+
+    for device in &mut merged.devices {
+        let layers = device.layers.iter_mut().flat_map(|l| l.macros.iter_mut());
+        for conf_macro in device.macros.iter_mut().chain(layers) {
+            let name = match &conf_macro.oneof_macro {
+                OneOfMacros::Named(name) => name,
+                _ => continue,
+            };
+            // Unknown names are reported by validation
+            if let Some(definition) = resolved.get(name) {
+                conf_macro.oneof_macro = definition.oneof_macro.clone();
+            }
+        }
+    }
+    merged.macros = resolved;
+
+    Ok(merged)
+}
+
+// Settings set by the later entry win, the ones it leaves out stay. Its bindings
+// replace the earlier ones of the same chord and trigger, layers with the same
+// name are merged as well.
+fn merge_device(earlier: &mut DeviceConfiguration, later: DeviceConfiguration) {
+    if later.input.is_some() {
+        earlier.input = later.input;
+    }
+    if later.report_ids.is_some() {
+        earlier.report_ids = later.report_ids;
+    }
+    if later.report_format.is_some() {
+        earlier.report_format = later.report_format;
+    }
+    if later.reconnect.is_some() {
+        earlier.reconnect = later.reconnect;
+    }
+
+    earlier.macros = merge_macros(mem::take(&mut earlier.macros), later.macros);
+    for layer in later.layers {
+        match earlier.layers.iter_mut().find(|l| l.name == layer.name) {
+            Some(existing) => {
+                existing.macros = merge_macros(mem::take(&mut existing.macros), layer.macros)
+            }
+            None => earlier.layers.push(layer),
+        }
+    }
+}
+
+fn merge_macros(mut earlier: Vec<Macro>, later: Vec<Macro>) -> Vec<Macro> {
+    earlier.retain(|e| !later.iter().any(|l| same_binding(e, l)));
+    earlier.extend(later);
+    earlier
+}
+
+// Trigger kind is compared, a different hold threshold still replaces the binding
+fn same_binding(a: &Macro, b: &Macro) -> bool {
+    let modifiers = |m: &Macro| m.modifiers.iter().cloned().collect::<BTreeSet<_>>();
+    a.key == b.key && modifiers(a) == modifiers(b) && a.trigger == b.trigger
+}
+
+// Follows references between named macros, coming back to one on the way is a cycle
+fn definition(macros: &HashMap<String, NamedMacro>, name: &str) -> Result<NamedMacro, String> {
+    let mut chain = vec![name];
+
+    loop {
+        match macros.get(chain[chain.len() - 1]) {
+            Some(NamedMacro {
+                oneof_macro: OneOfMacros::Named(next),
+            }) => {
+                let cycle = chain.contains(&next.as_str());
+                chain.push(next);
+                if cycle {
+                    return Err(format!("macro cycle {}", chain.join(" -> ")));
+                }
+            }
+            Some(definition) => return Ok(definition.clone()),
+            None => return Err(format!("unknown macro {}", chain[chain.len() - 1])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::keyboard::Key;
+    use std::{env, process};
+
+    // Directory of files written by a test, removed with them once dropped
+    struct TempFiles {
+        dir: PathBuf,
+        // First of the written files
+        path: PathBuf,
+    }
+
+    impl Drop for TempFiles {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    // Writes the files into a new directory
+    fn write(name: &str, files: &[(&str, &str)]) -> TempFiles {
+        let dir = env::temp_dir().join(format!("keyboard2deck-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            fs::write(dir.join(file), content).unwrap();
+        }
+        TempFiles {
+            path: dir.join(files[0].0),
+            dir,
+        }
+    }
+
+    fn load(path: &Path) -> Result<Config, String> {
+        config::load(path.to_str().unwrap(), Format::of(path)).map_err(|e| e.to_string())
+    }
+
+    fn shortcut_keys(conf_macro: &Macro) -> Vec<Key> {
+        match &conf_macro.oneof_macro {
+            OneOfMacros::Shortcut(shortcut) => shortcut.keys.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    const COMMON: &str = r#"
+macros:
+  save:
+    shortcut: { keys: ["ControlLeft", "S"] }
+devices:
+  - vid: 1
+    pid: 2
+    input: evdev
+    reconnect: { max_retries: 3 }
+    macros:
+      - key: "A"
+        shortcut: { keys: ["B"] }
+      - key: "C"
+        macro: "save"
+    layers:
+      - name: "fn"
+        macros:
+          - key: "A"
+            shortcut: { keys: ["F1"] }
+"#;
+
+    const CONFIG: &str = r#"
+include:
+  - "common.yaml"
+macros:
+  save:
+    shortcut: { keys: ["ControlLeft", "ShiftLeft", "S"] }
+  save-all:
+    macro: "save"
+devices:
+  - vid: 1
+    pid: 2
+    report_format: boot
+    reconnect: { max_retries: 5 }
+    macros:
+      - key: "A"
+        macro: "save-all"
+      - key: "D"
+        shortcut: { keys: ["E"] }
+    layers:
+      - name: "fn"
+        macros:
+          - key: "B"
+            layer: { name: "fn", mode: "toggle" }
+"#;
+
+    #[test]
+    fn merges_included_files() {
+        let files = write("merge", &[("config.yaml", CONFIG), ("common.yaml", COMMON)]);
+        let config = load(&files.path).unwrap();
+        assert_eq!(config.devices.len(), 1);
+
+        // Settings left out by the including file stay, the ones it sets replace them
+        let device = &config.devices[0];
+        assert!(device.input() == config::Input::Evdev);
+        assert!(device.report_format() == config::ReportFormat::Boot);
+        assert_eq!(device.reconnect().max_retries, Some(5));
+
+        // Including file replaces the binding of A and the definition of save
+        let device = &config.devices[0];
+        let keys: Vec<Key> = device.macros.iter().map(|m| m.key.clone()).collect();
+        assert_eq!(keys, [Key::C, Key::A, Key::D]);
+        let save = vec![Key::ControlLeft, Key::ShiftLeft, Key::S];
+        assert_eq!(shortcut_keys(&device.macros[0]), save);
+        assert_eq!(shortcut_keys(&device.macros[1]), save);
+
+        // Layers of the same name are merged
+        assert_eq!(device.layers.len(), 1);
+        assert_eq!(device.layers[0].macros.len(), 2);
+    }
+
+    #[test]
+    fn replaces_binding_of_other_threshold() {
+        let hold = |threshold: u64, key: &str| {
+            format!(
+                "devices:\n  - vid: 1\n    pid: 2\n    macros:\n      - key: A\n        \
+                 trigger: hold\n        threshold_ms: {}\n        shortcut: {{ keys: [\"{}\"] }}\n",
+                threshold, key
+            )
+        };
+        let config = format!("include: [\"common.yaml\"]\n{}", hold(500, "C"));
+        let files = write(
+            "threshold",
+            &[("config.yaml", &config), ("common.yaml", &hold(300, "B"))],
+        );
+        let config = load(&files.path).unwrap();
+
+        let macros = &config.devices[0].macros;
+        assert_eq!(macros.len(), 1);
+        assert_eq!(shortcut_keys(&macros[0]), [Key::C]);
+        assert_eq!(macros[0].threshold_ms, Some(500));
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let files = write(
+            "include-cycle",
+            &[
+                ("a.yaml", "include: [\"b.yaml\"]\n"),
+                ("b.yaml", "include:\n  - \"a.yaml\"\n"),
+            ],
+        );
+        let error = load(&files.path).err().unwrap();
+        assert!(
+            error.contains("b.yaml:2:5: include[0]: include cycle "),
+            "{}",
+            error
+        );
+
+        let macros = "macros:\n  x:\n    macro: y\n  y:\n    macro: x\n";
+        let files = write("macro-cycle", &[("config.yaml", macros)]);
+        let error = load(&files.path).err().unwrap();
+        assert!(
+            error.contains("config.yaml:3:5: macros.x: macro cycle x -> y -> x"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("config.yaml:5:5: macros.y: macro cycle y -> x -> y"),
+            "{}",
+            error
+        );
+
+        // References to unknown macros are found by validation
+        let unknown =
+            "devices:\n  - vid: 1\n    pid: 2\n    macros:\n      - key: A\n        macro: z\n";
+        let files = write("macro-unknown", &[("config.yaml", unknown)]);
+        let error = load(&files.path).err().unwrap();
+        assert!(
+            error.contains("config.yaml:6:16: devices[0].macros[0].macro: unknown macro z"),
+            "{}",
+            error
+        );
+    }
+}
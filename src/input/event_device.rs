@@ -0,0 +1,161 @@
+// Linux input event devices (/dev/input/event*).
+// Devices are grabbed exclusively, so their keys do not reach other applications.
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::time::Duration;
+
+use super::{DeviceInfo, DeviceSelector, InputBackend, InputError, InputReader};
+use crate::keyboard::{Key, KeyEvent, KeyEventType};
+
+// Values of EV_KEY events, autorepeat is ignored
+const KEY_RELEASED: i32 = 0;
+const KEY_PRESSED: i32 = 1;
+
+pub struct EvdevBackend;
+
+// All event devices of the keyboard, e.g. keys and media keys are separate devices
+pub struct EventDevices {
+    devices: Vec<evdev::Device>,
+}
+
+// Event devices do not know HID usages, USB interface is the suffix of the physical path
+fn device_info(path: &Path, device: &evdev::Device) -> DeviceInfo {
+    let interface_number = device
+        .physical_path()
+        .and_then(|phys| phys.rsplit_once("/input"))
+        .and_then(|(_, interface)| interface.parse().ok());
+
+    DeviceInfo {
+        vid: device.input_id().vendor(),
+        pid: device.input_id().product(),
+        name: device.name().unwrap_or("N/A").to_string(),
+        serial: device.unique_name().map(|s| s.to_string()),
+        interface_number,
+        usage_page: None,
+        usage: None,
+        path: path.display().to_string(),
+    }
+}
+
+fn is_keyboard(device: &evdev::Device) -> bool {
+    device.supported_keys().is_some()
+}
+
+impl InputBackend for EvdevBackend {
+    fn enumerate(&mut self) -> Vec<DeviceInfo> {
+        evdev::enumerate()
+            .filter(|(_, device)| is_keyboard(device))
+            .map(|(path, device)| device_info(&path, &device))
+            .collect()
+    }
+
+    fn open(&mut self, selector: &DeviceSelector) -> Result<Box<dyn InputReader>, InputError> {
+        match EventDevices::open(selector) {
+            Ok(devices) => Ok(Box::new(devices)),
+            Err(e) => Err(input_error(e)),
+        }
+    }
+}
+
+impl EventDevices {
+    fn open(selector: &DeviceSelector) -> io::Result<EventDevices> {
+        let mut devices = Vec::new();
+
+        for (path, mut device) in evdev::enumerate() {
+            if !is_keyboard(&device) || !selector.matches(&device_info(&path, &device)) {
+                continue;
+            }
+            device.grab().map_err(|e| {
+                io::Error::new(e.kind(), format!("cannot grab {}: {}", path.display(), e))
+            })?;
+            device.set_nonblocking(true)?;
+            devices.push(device);
+        }
+
+        if devices.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no event device found",
+            ));
+        }
+
+        Ok(EventDevices { devices })
+    }
+
+    // Waits at most the timeout for key events of any of the devices
+    fn read_events(&mut self, timeout: Duration) -> io::Result<Vec<KeyEvent>> {
+        let mut fds: Vec<libc::pollfd> = self
+            .devices
+            .iter()
+            .map(|d| libc::pollfd {
+                fd: d.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+
+        let res = unsafe {
+            libc::poll(
+                fds.as_mut_ptr(),
+                fds.len() as libc::nfds_t,
+                timeout.as_millis() as libc::c_int,
+            )
+        };
+        if res < 0 {
+            let e = io::Error::last_os_error();
+            return match e.kind() {
+                io::ErrorKind::Interrupted => Ok(Vec::new()),
+                _ => Err(e),
+            };
+        }
+
+        let mut events = Vec::new();
+        for (device, fd) in self.devices.iter_mut().zip(fds.iter()) {
+            if fd.revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "device disconnected",
+                ));
+            }
+            if fd.revents & libc::POLLIN == 0 {
+                continue;
+            }
+
+            let fetched = match device.fetch_events() {
+                Ok(fetched) => fetched,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e),
+            };
+            for event in fetched {
+                if event.event_type() != evdev::EventType::KEY {
+                    continue;
+                }
+                let event_type = match event.value() {
+                    KEY_PRESSED => KeyEventType::PRESSED,
+                    KEY_RELEASED => KeyEventType::RELEASED,
+                    _ => continue,
+                };
+                if let Ok(key) = Key::try_from_linux_keycode(event.code()) {
+                    events.push(KeyEvent { key, event_type });
+                }
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+// Reads of unplugged event devices fail with ENODEV
+fn input_error(e: io::Error) -> InputError {
+    match e.raw_os_error() {
+        Some(libc::ENODEV) => InputError::Disconnected(e.to_string()),
+        _ => InputError::from(e),
+    }
+}
+
+impl InputReader for EventDevices {
+    fn read_timeout(&mut self, timeout: Duration) -> Result<Vec<KeyEvent>, InputError> {
+        self.read_events(timeout).map_err(input_error)
+    }
+}
@@ -0,0 +1,160 @@
+// HID reports read through hidapi.
+// Keys of the device are still delivered to the system.
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hidapi::{HidApi, HidDevice};
+
+use super::{DeviceInfo, DeviceSelector, InputBackend, InputError, InputReader};
+use crate::config::{ReportFormat, ReportIds};
+use crate::descriptor::{self, ReportLayout};
+use crate::keyboard::KeyEvent;
+use crate::report::ReportDecoder;
+
+pub struct HidrawBackend {
+    hid_api: Arc<Mutex<HidApi>>,
+    report_ids: ReportIds,
+    report_format: ReportFormat,
+}
+
+pub struct HidrawReader {
+    device: HidDevice,
+    decoder: ReportDecoder,
+    buf: Vec<u8>,
+}
+
+impl HidrawBackend {
+    pub fn new(
+        hid_api: Arc<Mutex<HidApi>>,
+        report_ids: ReportIds,
+        report_format: ReportFormat,
+    ) -> HidrawBackend {
+        HidrawBackend {
+            hid_api,
+            report_ids,
+            report_format,
+        }
+    }
+
+    // Reads and parses report descriptor of the interface which is opened.
+    // None means boot protocol is used.
+    fn report_layout(&self, vid: u16, pid: u16, interface: i32) -> Option<ReportLayout> {
+        let raw = descriptor::read_report_descriptor(vid, pid, interface)?;
+        match descriptor::parse(&raw) {
+            Ok(layout) if layout.has_keys() => Some(layout),
+            Ok(_) => {
+                println!("Report descriptor has no keys, using boot protocol");
+                None
+            }
+            Err(e) => {
+                println!(
+                    "Cannot parse report descriptor ({}), using boot protocol",
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+impl InputBackend for HidrawBackend {
+    fn enumerate(&mut self) -> Vec<DeviceInfo> {
+        let mut api = self.hid_api.lock().unwrap();
+        if let Err(e) = api.refresh_devices() {
+            println!("Cannot refresh HID devices ({})", e);
+        }
+
+        api.device_list().map(device_info).collect()
+    }
+
+    fn open(&mut self, selector: &DeviceSelector) -> Result<Box<dyn InputReader>, InputError> {
+        let info = {
+            let api = self.hid_api.lock().unwrap();
+            let info = api
+                .device_list()
+                .find(|d| selector.matches(&device_info(d)))
+                .cloned();
+            info.ok_or_else(|| {
+                InputError::Disconnected("no matching HID device found".to_string())
+            })?
+        };
+
+        // Descriptor has to be read before opening, libusb backend detaches kernel driver
+        let layout = match self.report_format {
+            ReportFormat::Auto => {
+                self.report_layout(selector.vid, selector.pid, info.interface_number())
+            }
+            _ => None,
+        };
+
+        check_access(&info.path().to_string_lossy())?;
+        let device = {
+            let api = self.hid_api.lock().unwrap();
+            info.open_device(&api)
+                .map_err(|e| InputError::Other(e.to_string()))?
+        };
+
+        let decoder = ReportDecoder::new(self.report_ids.clone(), self.report_format, layout);
+        let buf = vec![0u8; decoder.buffer_size()];
+
+        Ok(Box::new(HidrawReader {
+            device,
+            decoder,
+            buf,
+        }))
+    }
+}
+
+fn device_info(d: &hidapi::DeviceInfo) -> DeviceInfo {
+    DeviceInfo {
+        vid: d.vendor_id(),
+        pid: d.product_id(),
+        name: d.product_string().unwrap_or("N/A").to_string(),
+        serial: d.serial_number().map(|s| s.to_string()),
+        interface_number: Some(d.interface_number()),
+        usage_page: Some(d.usage_page()),
+        usage: Some(d.usage()),
+        path: d.path().to_string_lossy().into_owned(),
+    }
+}
+
+// hidapi reports only messages, so the device node is opened first to tell
+// whether access is denied. Other backends have no node in the file system.
+#[cfg(target_os = "linux")]
+fn check_access(path: &str) -> Result<(), InputError> {
+    if !path.starts_with("/dev/") {
+        return Ok(());
+    }
+    match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+    {
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            Err(InputError::PermissionDenied(format!("{}: {}", path, e)))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn check_access(_path: &str) -> Result<(), InputError> {
+    Ok(())
+}
+
+impl InputReader for HidrawReader {
+    // Reads fail when the device is unplugged, whether it is still present is checked by the caller
+    fn read_timeout(&mut self, timeout: Duration) -> Result<Vec<KeyEvent>, InputError> {
+        let res = self
+            .device
+            .read_timeout(&mut self.buf[..], timeout.as_millis() as i32)
+            .map_err(|e| InputError::Disconnected(e.to_string()))?;
+
+        // Timed out without a report
+        if res == 0 {
+            return Ok(Vec::new());
+        }
+
+        Ok(self.decoder.events(&self.buf[..res]))
+    }
+}
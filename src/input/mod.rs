@@ -0,0 +1,224 @@
+// Sources of key events. Every backend finds and opens devices in its own way,
+// all of them produce the same stream of key events.
+#[cfg(target_os = "linux")]
+pub mod event_device;
+pub mod hidraw;
+
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::config::{DeviceConfiguration, Input};
+use crate::error::Error;
+use crate::keyboard::KeyEvent;
+
+// Connected device as seen by a backend.
+// Fields a backend does not know about are None.
+pub struct DeviceInfo {
+    pub vid: u16,
+    pub pid: u16,
+    pub name: String,
+    pub serial: Option<String>,
+    pub interface_number: Option<i32>,
+    pub usage_page: Option<u16>,
+    pub usage: Option<u16>,
+    pub path: String,
+}
+
+// Device selected in configuration, unset selectors match any device.
+// Used both to tell whether the device is connected and to open it.
+// Device entries with equal selectors in several files are the same device.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DeviceSelector {
+    pub vid: u16,
+    pub pid: u16,
+    pub serial: Option<String>,
+    pub interface_number: Option<i32>,
+    pub usage_page: Option<u16>,
+    pub usage: Option<u16>,
+    pub path: Option<String>,
+}
+
+impl DeviceSelector {
+    pub fn new(conf_device: &DeviceConfiguration) -> DeviceSelector {
+        DeviceSelector {
+            vid: conf_device.vid,
+            pid: conf_device.pid,
+            serial: conf_device.serial.clone(),
+            interface_number: conf_device.interface_number,
+            usage_page: conf_device.usage_page,
+            usage: conf_device.usage,
+            path: conf_device.path.clone(),
+        }
+    }
+
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        fn matches<T: PartialEq>(selector: &Option<T>, value: &Option<T>) -> bool {
+            selector.is_none() || selector == value
+        }
+
+        device.vid == self.vid
+            && device.pid == self.pid
+            && matches(&self.serial, &device.serial)
+            && matches(&self.interface_number, &device.interface_number)
+            && matches(&self.usage_page, &device.usage_page)
+            && matches(&self.usage, &device.usage)
+            && matches(&self.path, &Some(device.path.clone()))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InputError {
+    // Device is gone, it is waited for until it is plugged in again
+    Disconnected(String),
+    // Retrying does not help, the user has to grant access
+    PermissionDenied(String),
+    Other(String),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::Disconnected(e) => write!(f, "device disconnected: {}", e),
+            InputError::PermissionDenied(e) => write!(f, "permission denied: {}", e),
+            InputError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for InputError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => InputError::PermissionDenied(e.to_string()),
+            io::ErrorKind::NotFound | io::ErrorKind::BrokenPipe => {
+                InputError::Disconnected(e.to_string())
+            }
+            _ => InputError::Other(e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "device VID {} PID {}", self.vid, self.pid)?;
+        if let Some(serial) = &self.serial {
+            write!(f, " SN {}", serial)?;
+        }
+        Ok(())
+    }
+}
+
+pub trait InputBackend: Send {
+    // Lists devices currently available to the backend
+    fn enumerate(&mut self) -> Vec<DeviceInfo>;
+
+    // Opens the device matching the selector for reading key events
+    fn open(&mut self, selector: &DeviceSelector) -> Result<Box<dyn InputReader>, InputError>;
+}
+
+pub trait InputReader {
+    // Waits at most the timeout for key events, no events means timeout
+    fn read_timeout(&mut self, timeout: Duration) -> Result<Vec<KeyEvent>, InputError>;
+}
+
+// Creates backend selected for the device in configuration
+pub fn backend(
+    conf_device: &DeviceConfiguration,
+    hid_api: &Arc<Mutex<hidapi::HidApi>>,
+) -> Result<Box<dyn InputBackend>, Error> {
+    match conf_device.input() {
+        Input::Hidraw => Ok(Box::new(hidraw::HidrawBackend::new(
+            hid_api.clone(),
+            conf_device.report_ids(),
+            conf_device.report_format(),
+        ))),
+        #[cfg(target_os = "linux")]
+        Input::Evdev => Ok(Box::new(event_device::EvdevBackend)),
+        #[cfg(not(target_os = "linux"))]
+        Input::Evdev => Err(Error::Config(
+            "evdev input is available on Linux only".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(serial: &str, interface_number: i32, path: &str) -> DeviceInfo {
+        DeviceInfo {
+            vid: 1,
+            pid: 2,
+            name: "deck".to_string(),
+            serial: Some(serial.to_string()),
+            interface_number: Some(interface_number),
+            usage_page: Some(0x01),
+            usage: Some(0x06),
+            path: path.to_string(),
+        }
+    }
+
+    fn selector() -> DeviceSelector {
+        DeviceSelector {
+            vid: 1,
+            pid: 2,
+            serial: None,
+            interface_number: None,
+            usage_page: None,
+            usage: None,
+            path: None,
+        }
+    }
+
+    #[test]
+    fn selects_devices_with_same_vid_and_pid() {
+        let devices = [
+            info("A1", 0, "/dev/hidraw0"),
+            info("A1", 1, "/dev/hidraw1"),
+            info("B2", 0, "/dev/hidraw2"),
+        ];
+        let matching = |selector: &DeviceSelector| -> Vec<&str> {
+            devices
+                .iter()
+                .filter(|d| selector.matches(d))
+                .map(|d| d.path.as_str())
+                .collect()
+        };
+
+        assert_eq!(matching(&selector()).len(), 3);
+
+        let second = DeviceSelector {
+            serial: Some("B2".to_string()),
+            ..selector()
+        };
+        assert_eq!(matching(&second), ["/dev/hidraw2"]);
+
+        let interface = DeviceSelector {
+            serial: Some("A1".to_string()),
+            interface_number: Some(1),
+            ..selector()
+        };
+        assert_eq!(matching(&interface), ["/dev/hidraw1"]);
+
+        let path = DeviceSelector {
+            path: Some("/dev/hidraw0".to_string()),
+            ..selector()
+        };
+        assert_eq!(matching(&path), ["/dev/hidraw0"]);
+
+        let other_usage = DeviceSelector {
+            usage_page: Some(0x0C),
+            ..selector()
+        };
+        assert!(matching(&other_usage).is_empty());
+
+        let other_pid = DeviceSelector {
+            pid: 3,
+            ..selector()
+        };
+        assert!(matching(&other_pid).is_empty());
+    }
+}
@@ -1,10 +1,30 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::result::Result;
+use std::sync::OnceLock;
+
+use crate::descriptor::{PAGE_CONSUMER, PAGE_KEYBOARD};
+use crate::error::Error;
 
 const KEY_ERR_OVF: u8 = 0x01;
+const KEY_ERR_UNDEFINED: u8 = 0x03;
+// Highest Consumer page usage mapped to a key
+const LAST_CONSUMER_USAGE: u16 = 0x22A;
+
+// Modifier keys in the order of their bits in the first byte of the boot report
+const MODIFIER_KEYS: [Key; 8] = [
+    Key::ControlLeft,
+    Key::ShiftLeft,
+    Key::AltLeft,
+    Key::MetaLeft,
+    Key::ControlRight,
+    Key::ShiftRight,
+    Key::AltRight,
+    Key::MetaRight,
+];
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum KeyEventType {
     PRESSED,
     RELEASED,
@@ -17,106 +37,149 @@ pub struct KeyEvent {
 }
 
 pub struct Keyboard {
-    previous_events: Vec<Key>,
+    previous_keys: Vec<Key>,
+    previous_modifiers: u8,
+    previous_bitmap: Vec<u8>,
 }
 
 impl Keyboard {
     pub fn new() -> Keyboard {
         Keyboard {
-            previous_events: Vec::with_capacity(6),
+            previous_keys: Vec::with_capacity(6),
+            previous_modifiers: 0,
+            previous_bitmap: Vec::new(),
         }
     }
 
-    pub fn events(&mut self, buf: &[u8; 18], length: usize) -> Vec<KeyEvent> {
+    // Decodes boot protocol report (without report ID)
+    pub fn events(&mut self, report: &[u8]) -> Vec<KeyEvent> {
         let mut events = Vec::new();
 
-        for n in 2..length {
-            let value = buf[n];
+        if let Some(modifiers) = report.first() {
+            self.modifier_events(*modifiers, &mut events);
+        }
+
+        let mut keys = Vec::with_capacity(6);
+        for &value in report.iter().skip(2) {
+            if value == 0 {
+                // Empty slot
+                continue;
+            }
 
-            if value <= KEY_ERR_OVF {
-                // Overflow, do nothing
-                break;
+            if (KEY_ERR_OVF..=KEY_ERR_UNDEFINED).contains(&value) {
+                // Rollover, state of keys is unknown and stays as it was
+                return events;
             }
 
-            let key = match Key::try_key_from(value) {
-                Ok(k) => k,
-                Err(_) => {
-                    println!("Unknown key with value {}", value);
+            match Key::try_key_from(value) {
+                Ok(k) if !keys.contains(&k) => keys.push(k),
+                Ok(_) => {}
+                Err(_) => println!("Unknown key with value {}", value),
+            }
+        }
+
+        events.extend(diff_keys(&mut self.previous_keys, keys));
+        events
+    }
+
+    // Decodes NKRO bitmap report (without report ID).
+    // Modifier byte is followed by bitmap where bit N of the bitmap is usage N.
+    pub fn bitmap_events(&mut self, report: &[u8]) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+
+        let (modifiers, bitmap) = match report.split_first() {
+            Some(split) => split,
+            None => return events,
+        };
+        self.modifier_events(*modifiers, &mut events);
+
+        self.previous_bitmap.resize(bitmap.len(), 0);
+
+        for (n, (current, previous)) in bitmap.iter().zip(self.previous_bitmap.iter()).enumerate() {
+            let changed = current ^ previous;
+
+            for bit in 0..8 {
+                if changed & (1 << bit) == 0 {
                     continue;
                 }
-            };
 
-            if self.map_previous_state(&key) {
-                continue;
+                let usage = n * 8 + bit;
+                let key = match u8::try_from(usage).map(Key::try_key_from) {
+                    // Modifiers are already decoded from the modifier byte
+                    Ok(Ok(k)) if !k.is_modifier() => k,
+                    Ok(Ok(_)) => continue,
+                    _ => {
+                        println!("Unknown key with value {}", usage);
+                        continue;
+                    }
+                };
+
+                events.push(KeyEvent {
+                    key,
+                    event_type: if current & (1 << bit) != 0 {
+                        KeyEventType::PRESSED
+                    } else {
+                        KeyEventType::RELEASED
+                    },
+                });
             }
+        }
+
+        self.previous_bitmap.copy_from_slice(bitmap);
+        events
+    }
+
+    // Diffs modifier byte against the previous report
+    fn modifier_events(&mut self, modifiers: u8, events: &mut Vec<KeyEvent>) {
+        let pressed = modifiers & !self.previous_modifiers;
+        let released = self.previous_modifiers & !modifiers;
 
+        for key in Key::modifiers_from(pressed) {
             events.push(KeyEvent {
-                key: key,
+                key,
                 event_type: KeyEventType::PRESSED,
-            })
+            });
         }
 
-        // Check for released keys
-        for val in self.previous_events.iter() {
-            if *val == Key::Unknown {
-                continue;
-            }
-
+        for key in Key::modifiers_from(released) {
             events.push(KeyEvent {
-                key: val.clone(),
+                key,
                 event_type: KeyEventType::RELEASED,
-            })
-        }
-
-        //Clear previous state
-        self.previous_events.clear();
-        //Set current keys as previous state
-        for event in events.iter() {
-            if event.event_type == KeyEventType::PRESSED {
-                self.previous_events.push(event.key.clone());
-            }
+            });
         }
 
-        return events;
+        self.previous_modifiers = modifiers;
     }
+}
 
-    fn map_previous_state(&mut self, key_code: &Key) -> bool {
-        for val in self.previous_events.iter_mut() {
-            if *key_code == *val {
-                // Remove key from previous state
-                *val = Key::Unknown;
-                return true;
-            }
+// Emits events for keys which changed state since the previous report
+pub fn diff_keys(previous: &mut Vec<Key>, keys: Vec<Key>) -> Vec<KeyEvent> {
+    let mut events = Vec::new();
+
+    for key in keys.iter() {
+        if !previous.contains(key) {
+            events.push(KeyEvent {
+                key: key.clone(),
+                event_type: KeyEventType::PRESSED,
+            });
         }
+    }
 
-        return false;
+    for key in previous.iter() {
+        if !keys.contains(key) {
+            events.push(KeyEvent {
+                key: key.clone(),
+                event_type: KeyEventType::RELEASED,
+            });
+        }
     }
-}
 
-// const KEY_MOD_LCTRL: u8 = 0x01;
-// const KEY_MOD_LSHIFT: u8 = 0x02;
-// const KEY_MOD_LALT: u8 = 0x04;
-// const KEY_MOD_LMETA: u8 = 0x08;
-// const KEY_MOD_RCTRL: u8 = 0x10;
-// const KEY_MOD_RSHIFT: u8 = 0x20;
-// const KEY_MOD_RALT: u8 = 0x40;
-// const KEY_MOD_RMETA: u8 = 0x80;
-// fn mod_code_to_rdev_key(code: u8, dest: &mut Vec<rdev::Key>) {
-//     dest.clear();
-//     if code & KEY_MOD_LCTRL != 0 {
-//         dest.push(rdev::Key::ControlLeft);
-//     }
-//     if code & KEY_MOD_RCTRL != 0 {
-//         dest.push(rdev::Key::ControlRight);
-//     }
-//     if code & KEY_MOD_LSHIFT != 0 {
-//         dest.push(rdev::Key::ShiftLeft);
-//     }
-//     // TODO: Better parsing of bit flags...
-// }
+    *previous = keys;
+    events
+}
 
 #[repr(u8)]
-#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq, Hash, PartialOrd, Ord)]
 pub enum Key {
     Unknown,
     A,
@@ -192,16 +255,174 @@ pub enum Key {
     Alt,
     AltLeft,
     AltRight,
+    MetaLeft,
+    MetaRight,
     Intlbackslash,
     Home,
     Insert,
     Delete,
     End,
     PrintScreen,
+    ScrollLock,
+    Pause,
+    PageUp,
+    PageDown,
+    Right,
+    Left,
+    Down,
+    Up,
+    NumLock,
+    KpSlash,
+    KpAsterisk,
+    KpMinus,
+    KpPlus,
+    KpEnter,
+    Kp1,
+    Kp2,
+    Kp3,
+    Kp4,
+    Kp5,
+    Kp6,
+    Kp7,
+    Kp8,
+    Kp9,
+    Kp0,
+    KpDot,
+    Application,
+    Power,
+    KpEqual,
+    F13,
+    F14,
+    F15,
+    F16,
+    F17,
+    F18,
+    F19,
+    F20,
+    F21,
+    F22,
+    F23,
+    F24,
+    Execute,
+    Help,
+    Menu,
+    Select,
+    Stop,
+    Again,
+    Undo,
+    Cut,
+    Copy,
+    Paste,
+    Find,
+    Mute,
+    VolumeUp,
+    VolumeDown,
+    LockingCapslock,
+    LockingNumLock,
+    LockingScrollLock,
+    KpComma,
+    KpEqualSign,
+    Ro,
+    KatakanaHiragana,
+    Yen,
+    Henkan,
+    Muhenkan,
+    KpJpComma,
+    Intl7,
+    Intl8,
+    Intl9,
+    Hangeul,
+    Hanja,
+    Katakana,
+    Hiragana,
+    ZenkakuHankaku,
+    Lang6,
+    Lang7,
+    Lang8,
+    Lang9,
+    AltErase,
+    SysReq,
+    Cancel,
+    Clear,
+    Prior,
+    Return,
+    Separator,
+    Out,
+    Oper,
+    ClearAgain,
+    CrSel,
+    ExSel,
+    Kp00,
+    Kp000,
+    ThousandsSeparator,
+    DecimalSeparator,
+    CurrencyUnit,
+    CurrencySubUnit,
+    KpLeftParen,
+    KpRightParen,
+    KpLeftBrace,
+    KpRightBrace,
+    KpTab,
+    KpBackspace,
+    KpA,
+    KpB,
+    KpC,
+    KpD,
+    KpE,
+    KpF,
+    KpXor,
+    KpCaret,
+    KpPercent,
+    KpLess,
+    KpGreater,
+    KpAmpersand,
+    KpDoubleAmpersand,
+    KpPipe,
+    KpDoublePipe,
+    KpColon,
+    KpHash,
+    KpSpace,
+    KpAt,
+    KpExclamation,
+    KpMemoryStore,
+    KpMemoryRecall,
+    KpMemoryClear,
+    KpMemoryAdd,
+    KpMemorySubtract,
+    KpMemoryMultiply,
+    KpMemoryDivide,
+    KpPlusMinus,
+    KpClear,
+    KpClearEntry,
+    KpBinary,
+    KpOctal,
+    KpDecimal,
+    KpHexadecimal,
+    Sleep,
+    BrightnessUp,
+    BrightnessDown,
+    Play,
+    MediaPause,
+    FastForward,
+    Rewind,
+    NextTrack,
+    PreviousTrack,
+    MediaStop,
+    Eject,
+    PlayPause,
+    Mail,
+    Calculator,
+    MyComputer,
+    WwwSearch,
+    WwwHome,
+    WwwBack,
+    WwwForward,
+    WwwRefresh,
+    Bookmarks,
 }
 
 impl Key {
-    fn try_key_from(value: u8) -> Result<Self, ()> {
+    pub fn try_key_from(value: u8) -> Result<Self, Error> {
         match value {
             0x04 => Ok(Key::A),
             0x05 => Ok(Key::B),
@@ -274,23 +495,176 @@ impl Key {
             0x4C => Ok(Key::Delete),
             0x4D => Ok(Key::End),
             0x46 => Ok(Key::PrintScreen),
-            _ => Err(()),
+            0x47 => Ok(Key::ScrollLock),
+            0x48 => Ok(Key::Pause),
+            0x4B => Ok(Key::PageUp),
+            0x4E => Ok(Key::PageDown),
+            0x4F => Ok(Key::Right),
+            0x50 => Ok(Key::Left),
+            0x51 => Ok(Key::Down),
+            0x52 => Ok(Key::Up),
+            0x53 => Ok(Key::NumLock),
+            0x54 => Ok(Key::KpSlash),
+            0x55 => Ok(Key::KpAsterisk),
+            0x56 => Ok(Key::KpMinus),
+            0x57 => Ok(Key::KpPlus),
+            0x58 => Ok(Key::KpEnter),
+            0x59 => Ok(Key::Kp1),
+            0x5A => Ok(Key::Kp2),
+            0x5B => Ok(Key::Kp3),
+            0x5C => Ok(Key::Kp4),
+            0x5D => Ok(Key::Kp5),
+            0x5E => Ok(Key::Kp6),
+            0x5F => Ok(Key::Kp7),
+            0x60 => Ok(Key::Kp8),
+            0x61 => Ok(Key::Kp9),
+            0x62 => Ok(Key::Kp0),
+            0x63 => Ok(Key::KpDot),
+            0x64 => Ok(Key::Intlbackslash),
+            0x65 => Ok(Key::Application),
+            0x66 => Ok(Key::Power),
+            0x67 => Ok(Key::KpEqual),
+            0x68 => Ok(Key::F13),
+            0x69 => Ok(Key::F14),
+            0x6A => Ok(Key::F15),
+            0x6B => Ok(Key::F16),
+            0x6C => Ok(Key::F17),
+            0x6D => Ok(Key::F18),
+            0x6E => Ok(Key::F19),
+            0x6F => Ok(Key::F20),
+            0x70 => Ok(Key::F21),
+            0x71 => Ok(Key::F22),
+            0x72 => Ok(Key::F23),
+            0x73 => Ok(Key::F24),
+            0x74 => Ok(Key::Execute),
+            0x75 => Ok(Key::Help),
+            0x76 => Ok(Key::Menu),
+            0x77 => Ok(Key::Select),
+            0x78 => Ok(Key::Stop),
+            0x79 => Ok(Key::Again),
+            0x7A => Ok(Key::Undo),
+            0x7B => Ok(Key::Cut),
+            0x7C => Ok(Key::Copy),
+            0x7D => Ok(Key::Paste),
+            0x7E => Ok(Key::Find),
+            0x7F => Ok(Key::Mute),
+            0x80 => Ok(Key::VolumeUp),
+            0x81 => Ok(Key::VolumeDown),
+            0x82 => Ok(Key::LockingCapslock),
+            0x83 => Ok(Key::LockingNumLock),
+            0x84 => Ok(Key::LockingScrollLock),
+            0x85 => Ok(Key::KpComma),
+            0x86 => Ok(Key::KpEqualSign),
+            0x87 => Ok(Key::Ro),
+            0x88 => Ok(Key::KatakanaHiragana),
+            0x89 => Ok(Key::Yen),
+            0x8A => Ok(Key::Henkan),
+            0x8B => Ok(Key::Muhenkan),
+            0x8C => Ok(Key::KpJpComma),
+            0x8D => Ok(Key::Intl7),
+            0x8E => Ok(Key::Intl8),
+            0x8F => Ok(Key::Intl9),
+            0x90 => Ok(Key::Hangeul),
+            0x91 => Ok(Key::Hanja),
+            0x92 => Ok(Key::Katakana),
+            0x93 => Ok(Key::Hiragana),
+            0x94 => Ok(Key::ZenkakuHankaku),
+            0x95 => Ok(Key::Lang6),
+            0x96 => Ok(Key::Lang7),
+            0x97 => Ok(Key::Lang8),
+            0x98 => Ok(Key::Lang9),
+            0x99 => Ok(Key::AltErase),
+            0x9A => Ok(Key::SysReq),
+            0x9B => Ok(Key::Cancel),
+            0x9C => Ok(Key::Clear),
+            0x9D => Ok(Key::Prior),
+            0x9E => Ok(Key::Return),
+            0x9F => Ok(Key::Separator),
+            0xA0 => Ok(Key::Out),
+            0xA1 => Ok(Key::Oper),
+            0xA2 => Ok(Key::ClearAgain),
+            0xA3 => Ok(Key::CrSel),
+            0xA4 => Ok(Key::ExSel),
+            0xB0 => Ok(Key::Kp00),
+            0xB1 => Ok(Key::Kp000),
+            0xB2 => Ok(Key::ThousandsSeparator),
+            0xB3 => Ok(Key::DecimalSeparator),
+            0xB4 => Ok(Key::CurrencyUnit),
+            0xB5 => Ok(Key::CurrencySubUnit),
+            0xB6 => Ok(Key::KpLeftParen),
+            0xB7 => Ok(Key::KpRightParen),
+            0xB8 => Ok(Key::KpLeftBrace),
+            0xB9 => Ok(Key::KpRightBrace),
+            0xBA => Ok(Key::KpTab),
+            0xBB => Ok(Key::KpBackspace),
+            0xBC => Ok(Key::KpA),
+            0xBD => Ok(Key::KpB),
+            0xBE => Ok(Key::KpC),
+            0xBF => Ok(Key::KpD),
+            0xC0 => Ok(Key::KpE),
+            0xC1 => Ok(Key::KpF),
+            0xC2 => Ok(Key::KpXor),
+            0xC3 => Ok(Key::KpCaret),
+            0xC4 => Ok(Key::KpPercent),
+            0xC5 => Ok(Key::KpLess),
+            0xC6 => Ok(Key::KpGreater),
+            0xC7 => Ok(Key::KpAmpersand),
+            0xC8 => Ok(Key::KpDoubleAmpersand),
+            0xC9 => Ok(Key::KpPipe),
+            0xCA => Ok(Key::KpDoublePipe),
+            0xCB => Ok(Key::KpColon),
+            0xCC => Ok(Key::KpHash),
+            0xCD => Ok(Key::KpSpace),
+            0xCE => Ok(Key::KpAt),
+            0xCF => Ok(Key::KpExclamation),
+            0xD0 => Ok(Key::KpMemoryStore),
+            0xD1 => Ok(Key::KpMemoryRecall),
+            0xD2 => Ok(Key::KpMemoryClear),
+            0xD3 => Ok(Key::KpMemoryAdd),
+            0xD4 => Ok(Key::KpMemorySubtract),
+            0xD5 => Ok(Key::KpMemoryMultiply),
+            0xD6 => Ok(Key::KpMemoryDivide),
+            0xD7 => Ok(Key::KpPlusMinus),
+            0xD8 => Ok(Key::KpClear),
+            0xD9 => Ok(Key::KpClearEntry),
+            0xDA => Ok(Key::KpBinary),
+            0xDB => Ok(Key::KpOctal),
+            0xDC => Ok(Key::KpDecimal),
+            0xDD => Ok(Key::KpHexadecimal),
+            0xE0 => Ok(Key::ControlLeft),
+            0xE1 => Ok(Key::ShiftLeft),
+            0xE2 => Ok(Key::AltLeft),
+            0xE3 => Ok(Key::MetaLeft),
+            0xE4 => Ok(Key::ControlRight),
+            0xE5 => Ok(Key::ShiftRight),
+            0xE6 => Ok(Key::AltRight),
+            0xE7 => Ok(Key::MetaRight),
+            _ => Err(Error::UnknownUsage {
+                page: PAGE_KEYBOARD,
+                usage: value as u16,
+            }),
         }
     }
 
-    pub fn try_mod_from(value: u8) -> Result<Self, ()> {
-        match value {
-            0x02 => Ok(Key::ShiftLeft),
-            0x20 => Ok(Key::ShiftRight),
-            0x01 => Ok(Key::ControlLeft),
-            0x10 => Ok(Key::ControlRight),
-            0x04 => Ok(Key::AltLeft),
-            0x40 => Ok(Key::AltRight),
-            _ => Err(()),
+    // Decodes modifier bitmask into the list of modifier keys
+    pub fn modifiers_from(bits: u8) -> Vec<Self> {
+        let mut keys = Vec::new();
+
+        for (n, key) in MODIFIER_KEYS.iter().enumerate() {
+            if bits & (1 << n) != 0 {
+                keys.push(key.clone());
+            }
         }
+
+        keys
+    }
+
+    pub fn is_modifier(&self) -> bool {
+        matches!(self.usage(), Some(0xE0..=0xE7))
     }
 
-    pub fn try_into_rdev(&self) -> Result<rdev::Key, ()> {
+    #[cfg(feature = "rdev")]
+    pub fn try_into_rdev(&self) -> Result<rdev::Key, Error> {
         match self {
             Key::A => Ok(rdev::Key::KeyA),
             Key::B => Ok(rdev::Key::KeyB),
@@ -338,10 +712,10 @@ impl Key {
             Key::LeftBrace => Ok(rdev::Key::LeftBracket),
             Key::Rightbrace => Ok(rdev::Key::RightBracket),
             Key::Backslash => Ok(rdev::Key::BackSlash),
-            Key::Hashtilde => Ok(rdev::Key::BackQuote),
+            Key::Hashtilde => Ok(rdev::Key::BackSlash),
             Key::Semicolon => Ok(rdev::Key::SemiColon),
             Key::Apostrophe => Ok(rdev::Key::Quote),
-            Key::Grave => Ok(rdev::Key::IntlBackslash),
+            Key::Grave => Ok(rdev::Key::BackQuote),
             Key::Intlbackslash => Ok(rdev::Key::IntlBackslash),
             Key::Comma => Ok(rdev::Key::Comma),
             Key::Dot => Ok(rdev::Key::Dot),
@@ -364,11 +738,832 @@ impl Key {
             Key::ControlLeft => Ok(rdev::Key::ControlLeft),
             Key::ControlRight => Ok(rdev::Key::ControlRight),
             Key::AltLeft => Ok(rdev::Key::Alt),
-            Key::AltRight => Ok(rdev::Key::Alt),
+            Key::AltRight => Ok(rdev::Key::AltGr),
             Key::Alt => Ok(rdev::Key::Alt),
+            Key::MetaLeft => Ok(rdev::Key::MetaLeft),
+            Key::MetaRight => Ok(rdev::Key::MetaRight),
             Key::Delete => Ok(rdev::Key::Delete),
             Self::PrintScreen => Ok(rdev::Key::PrintScreen),
-            _ => Err(()),
+            Key::ScrollLock => Ok(rdev::Key::ScrollLock),
+            Key::Pause => Ok(rdev::Key::Pause),
+            Key::Insert => Ok(rdev::Key::Insert),
+            Key::Home => Ok(rdev::Key::Home),
+            Key::End => Ok(rdev::Key::End),
+            Key::PageUp => Ok(rdev::Key::PageUp),
+            Key::PageDown => Ok(rdev::Key::PageDown),
+            Key::Right => Ok(rdev::Key::RightArrow),
+            Key::Left => Ok(rdev::Key::LeftArrow),
+            Key::Down => Ok(rdev::Key::DownArrow),
+            Key::Up => Ok(rdev::Key::UpArrow),
+            Key::NumLock => Ok(rdev::Key::NumLock),
+            Key::KpSlash => Ok(rdev::Key::KpDivide),
+            Key::KpAsterisk => Ok(rdev::Key::KpMultiply),
+            Key::KpMinus => Ok(rdev::Key::KpMinus),
+            Key::KpPlus => Ok(rdev::Key::KpPlus),
+            Key::KpEnter => Ok(rdev::Key::KpReturn),
+            Key::Kp1 => Ok(rdev::Key::Kp1),
+            Key::Kp2 => Ok(rdev::Key::Kp2),
+            Key::Kp3 => Ok(rdev::Key::Kp3),
+            Key::Kp4 => Ok(rdev::Key::Kp4),
+            Key::Kp5 => Ok(rdev::Key::Kp5),
+            Key::Kp6 => Ok(rdev::Key::Kp6),
+            Key::Kp7 => Ok(rdev::Key::Kp7),
+            Key::Kp8 => Ok(rdev::Key::Kp8),
+            Key::Kp9 => Ok(rdev::Key::Kp9),
+            Key::Kp0 => Ok(rdev::Key::Kp0),
+            Key::KpDot => Ok(rdev::Key::KpDelete),
+            // Keys without rdev counterpart are sent as raw X11 keycodes
+            #[cfg(target_os = "linux")]
+            other => match other.linux_keycode() {
+                Some(code) => Ok(rdev::Key::Unknown(code as u32 + 8)),
+                None => Err(self.unsupported_by_rdev()),
+            },
+            #[cfg(not(target_os = "linux"))]
+            _ => Err(self.unsupported_by_rdev()),
+        }
+    }
+
+    #[cfg(feature = "rdev")]
+    fn unsupported_by_rdev(&self) -> Error {
+        Error::UnsupportedKey {
+            key: self.clone(),
+            output: "rdev",
+        }
+    }
+
+    // Decodes usage from the Consumer page (media keys)
+    pub fn try_consumer_from(value: u16) -> Result<Self, Error> {
+        match value {
+            0x30 => Ok(Key::Power),
+            0x32 => Ok(Key::Sleep),
+            0x6F => Ok(Key::BrightnessUp),
+            0x70 => Ok(Key::BrightnessDown),
+            0xB0 => Ok(Key::Play),
+            0xB1 => Ok(Key::MediaPause),
+            0xB3 => Ok(Key::FastForward),
+            0xB4 => Ok(Key::Rewind),
+            0xB5 => Ok(Key::NextTrack),
+            0xB6 => Ok(Key::PreviousTrack),
+            0xB7 => Ok(Key::MediaStop),
+            0xB8 => Ok(Key::Eject),
+            0xCD => Ok(Key::PlayPause),
+            0xE2 => Ok(Key::Mute),
+            0xE9 => Ok(Key::VolumeUp),
+            0xEA => Ok(Key::VolumeDown),
+            0x18A => Ok(Key::Mail),
+            0x192 => Ok(Key::Calculator),
+            0x194 => Ok(Key::MyComputer),
+            0x221 => Ok(Key::WwwSearch),
+            0x223 => Ok(Key::WwwHome),
+            0x224 => Ok(Key::WwwBack),
+            0x225 => Ok(Key::WwwForward),
+            0x226 => Ok(Key::Stop),
+            0x227 => Ok(Key::WwwRefresh),
+            0x22A => Ok(Key::Bookmarks),
+            _ => Err(Error::UnknownUsage {
+                page: PAGE_CONSUMER,
+                usage: value,
+            }),
+        }
+    }
+
+    // HID usage ID (Keyboard/Keypad page) of the key
+    pub fn usage(&self) -> Option<u8> {
+        match self {
+            Key::A => Some(0x04),
+            Key::B => Some(0x05),
+            Key::C => Some(0x06),
+            Key::D => Some(0x07),
+            Key::E => Some(0x08),
+            Key::F => Some(0x09),
+            Key::G => Some(0x0A),
+            Key::H => Some(0x0B),
+            Key::I => Some(0x0C),
+            Key::J => Some(0x0D),
+            Key::K => Some(0x0E),
+            Key::L => Some(0x0F),
+            Key::M => Some(0x10),
+            Key::N => Some(0x11),
+            Key::O => Some(0x12),
+            Key::P => Some(0x13),
+            Key::Q => Some(0x14),
+            Key::R => Some(0x15),
+            Key::S => Some(0x16),
+            Key::T => Some(0x17),
+            Key::U => Some(0x18),
+            Key::V => Some(0x19),
+            Key::W => Some(0x1A),
+            Key::X => Some(0x1B),
+            Key::Y => Some(0x1C),
+            Key::Z => Some(0x1D),
+            Key::Num1 => Some(0x1E),
+            Key::Num2 => Some(0x1F),
+            Key::Num3 => Some(0x20),
+            Key::Num4 => Some(0x21),
+            Key::Num5 => Some(0x22),
+            Key::Num6 => Some(0x23),
+            Key::Num7 => Some(0x24),
+            Key::Num8 => Some(0x25),
+            Key::Num9 => Some(0x26),
+            Key::Num0 => Some(0x27),
+            Key::Enter => Some(0x28),
+            Key::Esc => Some(0x29),
+            Key::Backspace => Some(0x2A),
+            Key::Tab => Some(0x2B),
+            Key::Space => Some(0x2C),
+            Key::Minus => Some(0x2D),
+            Key::Equal => Some(0x2E),
+            Key::LeftBrace => Some(0x2F),
+            Key::Rightbrace => Some(0x30),
+            Key::Backslash => Some(0x31),
+            Key::Hashtilde => Some(0x32),
+            Key::Semicolon => Some(0x33),
+            Key::Apostrophe => Some(0x34),
+            Key::Grave => Some(0x35),
+            Key::Comma => Some(0x36),
+            Key::Dot => Some(0x37),
+            Key::Slash => Some(0x38),
+            Key::Capslock => Some(0x39),
+            Key::F1 => Some(0x3A),
+            Key::F2 => Some(0x3B),
+            Key::F3 => Some(0x3C),
+            Key::F4 => Some(0x3D),
+            Key::F5 => Some(0x3E),
+            Key::F6 => Some(0x3F),
+            Key::F7 => Some(0x40),
+            Key::F8 => Some(0x41),
+            Key::F9 => Some(0x42),
+            Key::F10 => Some(0x43),
+            Key::F11 => Some(0x44),
+            Key::F12 => Some(0x45),
+            Key::PrintScreen => Some(0x46),
+            Key::ScrollLock => Some(0x47),
+            Key::Pause => Some(0x48),
+            Key::Insert => Some(0x49),
+            Key::Home => Some(0x4A),
+            Key::PageUp => Some(0x4B),
+            Key::Delete => Some(0x4C),
+            Key::End => Some(0x4D),
+            Key::PageDown => Some(0x4E),
+            Key::Right => Some(0x4F),
+            Key::Left => Some(0x50),
+            Key::Down => Some(0x51),
+            Key::Up => Some(0x52),
+            Key::NumLock => Some(0x53),
+            Key::KpSlash => Some(0x54),
+            Key::KpAsterisk => Some(0x55),
+            Key::KpMinus => Some(0x56),
+            Key::KpPlus => Some(0x57),
+            Key::KpEnter => Some(0x58),
+            Key::Kp1 => Some(0x59),
+            Key::Kp2 => Some(0x5A),
+            Key::Kp3 => Some(0x5B),
+            Key::Kp4 => Some(0x5C),
+            Key::Kp5 => Some(0x5D),
+            Key::Kp6 => Some(0x5E),
+            Key::Kp7 => Some(0x5F),
+            Key::Kp8 => Some(0x60),
+            Key::Kp9 => Some(0x61),
+            Key::Kp0 => Some(0x62),
+            Key::KpDot => Some(0x63),
+            Key::Intlbackslash => Some(0x64),
+            Key::Application => Some(0x65),
+            Key::Power => Some(0x66),
+            Key::KpEqual => Some(0x67),
+            Key::F13 => Some(0x68),
+            Key::F14 => Some(0x69),
+            Key::F15 => Some(0x6A),
+            Key::F16 => Some(0x6B),
+            Key::F17 => Some(0x6C),
+            Key::F18 => Some(0x6D),
+            Key::F19 => Some(0x6E),
+            Key::F20 => Some(0x6F),
+            Key::F21 => Some(0x70),
+            Key::F22 => Some(0x71),
+            Key::F23 => Some(0x72),
+            Key::F24 => Some(0x73),
+            Key::Execute => Some(0x74),
+            Key::Help => Some(0x75),
+            Key::Menu => Some(0x76),
+            Key::Select => Some(0x77),
+            Key::Stop => Some(0x78),
+            Key::Again => Some(0x79),
+            Key::Undo => Some(0x7A),
+            Key::Cut => Some(0x7B),
+            Key::Copy => Some(0x7C),
+            Key::Paste => Some(0x7D),
+            Key::Find => Some(0x7E),
+            Key::Mute => Some(0x7F),
+            Key::VolumeUp => Some(0x80),
+            Key::VolumeDown => Some(0x81),
+            Key::LockingCapslock => Some(0x82),
+            Key::LockingNumLock => Some(0x83),
+            Key::LockingScrollLock => Some(0x84),
+            Key::KpComma => Some(0x85),
+            Key::KpEqualSign => Some(0x86),
+            Key::Ro => Some(0x87),
+            Key::KatakanaHiragana => Some(0x88),
+            Key::Yen => Some(0x89),
+            Key::Henkan => Some(0x8A),
+            Key::Muhenkan => Some(0x8B),
+            Key::KpJpComma => Some(0x8C),
+            Key::Intl7 => Some(0x8D),
+            Key::Intl8 => Some(0x8E),
+            Key::Intl9 => Some(0x8F),
+            Key::Hangeul => Some(0x90),
+            Key::Hanja => Some(0x91),
+            Key::Katakana => Some(0x92),
+            Key::Hiragana => Some(0x93),
+            Key::ZenkakuHankaku => Some(0x94),
+            Key::Lang6 => Some(0x95),
+            Key::Lang7 => Some(0x96),
+            Key::Lang8 => Some(0x97),
+            Key::Lang9 => Some(0x98),
+            Key::AltErase => Some(0x99),
+            Key::SysReq => Some(0x9A),
+            Key::Cancel => Some(0x9B),
+            Key::Clear => Some(0x9C),
+            Key::Prior => Some(0x9D),
+            Key::Return => Some(0x9E),
+            Key::Separator => Some(0x9F),
+            Key::Out => Some(0xA0),
+            Key::Oper => Some(0xA1),
+            Key::ClearAgain => Some(0xA2),
+            Key::CrSel => Some(0xA3),
+            Key::ExSel => Some(0xA4),
+            Key::Kp00 => Some(0xB0),
+            Key::Kp000 => Some(0xB1),
+            Key::ThousandsSeparator => Some(0xB2),
+            Key::DecimalSeparator => Some(0xB3),
+            Key::CurrencyUnit => Some(0xB4),
+            Key::CurrencySubUnit => Some(0xB5),
+            Key::KpLeftParen => Some(0xB6),
+            Key::KpRightParen => Some(0xB7),
+            Key::KpLeftBrace => Some(0xB8),
+            Key::KpRightBrace => Some(0xB9),
+            Key::KpTab => Some(0xBA),
+            Key::KpBackspace => Some(0xBB),
+            Key::KpA => Some(0xBC),
+            Key::KpB => Some(0xBD),
+            Key::KpC => Some(0xBE),
+            Key::KpD => Some(0xBF),
+            Key::KpE => Some(0xC0),
+            Key::KpF => Some(0xC1),
+            Key::KpXor => Some(0xC2),
+            Key::KpCaret => Some(0xC3),
+            Key::KpPercent => Some(0xC4),
+            Key::KpLess => Some(0xC5),
+            Key::KpGreater => Some(0xC6),
+            Key::KpAmpersand => Some(0xC7),
+            Key::KpDoubleAmpersand => Some(0xC8),
+            Key::KpPipe => Some(0xC9),
+            Key::KpDoublePipe => Some(0xCA),
+            Key::KpColon => Some(0xCB),
+            Key::KpHash => Some(0xCC),
+            Key::KpSpace => Some(0xCD),
+            Key::KpAt => Some(0xCE),
+            Key::KpExclamation => Some(0xCF),
+            Key::KpMemoryStore => Some(0xD0),
+            Key::KpMemoryRecall => Some(0xD1),
+            Key::KpMemoryClear => Some(0xD2),
+            Key::KpMemoryAdd => Some(0xD3),
+            Key::KpMemorySubtract => Some(0xD4),
+            Key::KpMemoryMultiply => Some(0xD5),
+            Key::KpMemoryDivide => Some(0xD6),
+            Key::KpPlusMinus => Some(0xD7),
+            Key::KpClear => Some(0xD8),
+            Key::KpClearEntry => Some(0xD9),
+            Key::KpBinary => Some(0xDA),
+            Key::KpOctal => Some(0xDB),
+            Key::KpDecimal => Some(0xDC),
+            Key::KpHexadecimal => Some(0xDD),
+            Key::ControlLeft => Some(0xE0),
+            Key::ShiftLeft => Some(0xE1),
+            Key::AltLeft => Some(0xE2),
+            Key::MetaLeft => Some(0xE3),
+            Key::ControlRight => Some(0xE4),
+            Key::ShiftRight => Some(0xE5),
+            Key::AltRight => Some(0xE6),
+            Key::MetaRight => Some(0xE7),
+            _ => None,
+        }
+    }
+
+    // Linux input event code (KEY_*) as assigned by the kernel HID driver
+    pub fn linux_keycode(&self) -> Option<u16> {
+        match self {
+            Key::A => Some(30),
+            Key::B => Some(48),
+            Key::C => Some(46),
+            Key::D => Some(32),
+            Key::E => Some(18),
+            Key::F => Some(33),
+            Key::G => Some(34),
+            Key::H => Some(35),
+            Key::I => Some(23),
+            Key::J => Some(36),
+            Key::K => Some(37),
+            Key::L => Some(38),
+            Key::M => Some(50),
+            Key::N => Some(49),
+            Key::O => Some(24),
+            Key::P => Some(25),
+            Key::Q => Some(16),
+            Key::R => Some(19),
+            Key::S => Some(31),
+            Key::T => Some(20),
+            Key::U => Some(22),
+            Key::V => Some(47),
+            Key::W => Some(17),
+            Key::X => Some(45),
+            Key::Y => Some(21),
+            Key::Z => Some(44),
+            Key::Num1 => Some(2),
+            Key::Num2 => Some(3),
+            Key::Num3 => Some(4),
+            Key::Num4 => Some(5),
+            Key::Num5 => Some(6),
+            Key::Num6 => Some(7),
+            Key::Num7 => Some(8),
+            Key::Num8 => Some(9),
+            Key::Num9 => Some(10),
+            Key::Num0 => Some(11),
+            Key::Enter => Some(28),
+            Key::Esc => Some(1),
+            Key::Backspace => Some(14),
+            Key::Tab => Some(15),
+            Key::Space => Some(57),
+            Key::Minus => Some(12),
+            Key::Equal => Some(13),
+            Key::LeftBrace => Some(26),
+            Key::Rightbrace => Some(27),
+            Key::Backslash => Some(43),
+            Key::Hashtilde => Some(43),
+            Key::Semicolon => Some(39),
+            Key::Apostrophe => Some(40),
+            Key::Grave => Some(41),
+            Key::Comma => Some(51),
+            Key::Dot => Some(52),
+            Key::Slash => Some(53),
+            Key::Capslock => Some(58),
+            Key::F1 => Some(59),
+            Key::F2 => Some(60),
+            Key::F3 => Some(61),
+            Key::F4 => Some(62),
+            Key::F5 => Some(63),
+            Key::F6 => Some(64),
+            Key::F7 => Some(65),
+            Key::F8 => Some(66),
+            Key::F9 => Some(67),
+            Key::F10 => Some(68),
+            Key::F11 => Some(87),
+            Key::F12 => Some(88),
+            Key::PrintScreen => Some(99),
+            Key::ScrollLock => Some(70),
+            Key::Pause => Some(119),
+            Key::Insert => Some(110),
+            Key::Home => Some(102),
+            Key::PageUp => Some(104),
+            Key::Delete => Some(111),
+            Key::End => Some(107),
+            Key::PageDown => Some(109),
+            Key::Right => Some(106),
+            Key::Left => Some(105),
+            Key::Down => Some(108),
+            Key::Up => Some(103),
+            Key::NumLock => Some(69),
+            Key::KpSlash => Some(98),
+            Key::KpAsterisk => Some(55),
+            Key::KpMinus => Some(74),
+            Key::KpPlus => Some(78),
+            Key::KpEnter => Some(96),
+            Key::Kp1 => Some(79),
+            Key::Kp2 => Some(80),
+            Key::Kp3 => Some(81),
+            Key::Kp4 => Some(75),
+            Key::Kp5 => Some(76),
+            Key::Kp6 => Some(77),
+            Key::Kp7 => Some(71),
+            Key::Kp8 => Some(72),
+            Key::Kp9 => Some(73),
+            Key::Kp0 => Some(82),
+            Key::KpDot => Some(83),
+            Key::Intlbackslash => Some(86),
+            Key::Application => Some(127),
+            Key::Power => Some(116),
+            Key::KpEqual => Some(117),
+            Key::F13 => Some(183),
+            Key::F14 => Some(184),
+            Key::F15 => Some(185),
+            Key::F16 => Some(186),
+            Key::F17 => Some(187),
+            Key::F18 => Some(188),
+            Key::F19 => Some(189),
+            Key::F20 => Some(190),
+            Key::F21 => Some(191),
+            Key::F22 => Some(192),
+            Key::F23 => Some(193),
+            Key::F24 => Some(194),
+            Key::Execute => Some(134),
+            Key::Help => Some(138),
+            Key::Menu => Some(130),
+            Key::Select => Some(132),
+            Key::Stop => Some(128),
+            Key::Again => Some(129),
+            Key::Undo => Some(131),
+            Key::Cut => Some(137),
+            Key::Copy => Some(133),
+            Key::Paste => Some(135),
+            Key::Find => Some(136),
+            Key::Mute => Some(113),
+            Key::VolumeUp => Some(115),
+            Key::VolumeDown => Some(114),
+            Key::KpComma => Some(121),
+            Key::Ro => Some(89),
+            Key::KatakanaHiragana => Some(93),
+            Key::Yen => Some(124),
+            Key::Henkan => Some(92),
+            Key::Muhenkan => Some(94),
+            Key::KpJpComma => Some(95),
+            Key::Hangeul => Some(122),
+            Key::Hanja => Some(123),
+            Key::Katakana => Some(90),
+            Key::Hiragana => Some(91),
+            Key::ZenkakuHankaku => Some(85),
+            Key::Clear => Some(111),
+            Key::KpLeftParen => Some(179),
+            Key::KpRightParen => Some(180),
+            Key::KpClear => Some(111),
+            Key::ControlLeft => Some(29),
+            Key::ShiftLeft => Some(42),
+            Key::AltLeft => Some(56),
+            Key::MetaLeft => Some(125),
+            Key::ControlRight => Some(97),
+            Key::ShiftRight => Some(54),
+            Key::AltRight => Some(100),
+            Key::MetaRight => Some(126),
+            Key::Alt => Some(56),
+            Key::Sleep => Some(142),
+            Key::BrightnessUp => Some(225),
+            Key::BrightnessDown => Some(224),
+            Key::Play => Some(207),
+            Key::MediaPause => Some(201),
+            Key::FastForward => Some(208),
+            Key::Rewind => Some(168),
+            Key::NextTrack => Some(163),
+            Key::PreviousTrack => Some(165),
+            Key::MediaStop => Some(166),
+            Key::Eject => Some(161),
+            Key::PlayPause => Some(164),
+            Key::Mail => Some(155),
+            Key::Calculator => Some(140),
+            Key::MyComputer => Some(144),
+            Key::WwwSearch => Some(217),
+            Key::WwwHome => Some(172),
+            Key::WwwBack => Some(158),
+            Key::WwwForward => Some(159),
+            Key::WwwRefresh => Some(173),
+            Key::Bookmarks => Some(156),
+            _ => None,
+        }
+    }
+
+    // Keys of the Keyboard/Keypad page followed by keys of the Consumer page,
+    // keys on both pages are yielded once
+    pub fn all() -> impl Iterator<Item = Key> {
+        (0..=u8::MAX)
+            .filter_map(|usage| Key::try_key_from(usage).ok())
+            .chain(
+                (0..=LAST_CONSUMER_USAGE)
+                    .filter_map(|usage| Key::try_consumer_from(usage).ok())
+                    .filter(|key| key.usage().is_none()),
+            )
+    }
+
+    // Key of the Linux input event code, keyboard page keys are preferred
+    pub fn try_from_linux_keycode(code: u16) -> Result<Self, Error> {
+        static KEYS: OnceLock<HashMap<u16, Key>> = OnceLock::new();
+
+        let keys = KEYS.get_or_init(|| {
+            let mut keys = HashMap::new();
+            for key in Key::all() {
+                if let Some(code) = key.linux_keycode() {
+                    keys.entry(code).or_insert(key);
+                }
+            }
+            keys
+        });
+        keys.get(&code).cloned().ok_or(Error::UnknownKeycode(code))
+    }
+}
+
+// Events as (key, pressed) pairs, for comparing in tests
+#[cfg(test)]
+pub fn summary(events: Vec<KeyEvent>) -> Vec<(Key, bool)> {
+    events
+        .into_iter()
+        .map(|e| (e.key, e.event_type == KeyEventType::PRESSED))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_reserved(usage: u8) -> bool {
+        (0xA5..=0xAF).contains(&usage) || (0xDE..=0xDF).contains(&usage)
+    }
+
+    #[test]
+    fn usage_table_round_trip() {
+        for usage in 0x04..=0xE7u8 {
+            if is_reserved(usage) {
+                assert!(Key::try_key_from(usage).is_err(), "usage {:#04x}", usage);
+                continue;
+            }
+
+            let key = Key::try_key_from(usage)
+                .unwrap_or_else(|_| panic!("usage {:#04x} is not mapped", usage));
+            assert_eq!(key.usage(), Some(usage), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn all_keys_once() {
+        let keys: Vec<Key> = Key::all().collect();
+        let unique: std::collections::BTreeSet<&Key> = keys.iter().collect();
+        assert_eq!(unique.len(), keys.len());
+
+        // Keys on both pages are still there
+        for usage in 0..=LAST_CONSUMER_USAGE {
+            if let Ok(key) = Key::try_consumer_from(usage) {
+                assert!(keys.contains(&key), "{:?}", key);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "rdev"))]
+    fn rdev_round_trip() {
+        use std::collections::HashMap;
+
+        // Keys sent as the same rdev key have to be the same key for Linux as well
+        let mut sent_as: HashMap<rdev::Key, Vec<Key>> = HashMap::new();
+        for key in Key::all() {
+            match key.try_into_rdev() {
+                Ok(rdev_key) => sent_as.entry(rdev_key).or_default().push(key),
+                // Only keys without Linux counterpart cannot be sent
+                Err(_) => assert_eq!(key.linux_keycode(), None, "{:?}", key),
+            }
+        }
+
+        for (rdev_key, keys) in sent_as {
+            let code = keys[0].linux_keycode();
+            for key in keys.iter() {
+                assert_eq!(key.linux_keycode(), code, "{:?} sent as {:?}", keys, rdev_key);
+            }
+
+            // Raw X11 keycodes lead back to a key with the same Linux keycode
+            if let rdev::Key::Unknown(raw) = rdev_key {
+                let key = Key::try_from_linux_keycode(raw as u16 - 8);
+                assert_eq!(key.map(|k| k.linux_keycode()), Ok(code), "{:?}", keys);
+            }
+        }
+    }
+
+    #[test]
+    fn linux_keycode_round_trip() {
+        assert_eq!(Key::try_from_linux_keycode(30), Ok(Key::A));
+        assert_eq!(Key::try_from_linux_keycode(56), Ok(Key::AltLeft));
+        assert_eq!(Key::try_from_linux_keycode(164), Ok(Key::PlayPause));
+        assert!(Key::try_from_linux_keycode(0).is_err());
+
+        for usage in 0x04..=0xE7u8 {
+            let key = match Key::try_key_from(usage) {
+                Ok(k) => k,
+                Err(_) => continue,
+            };
+            let code = match key.linux_keycode() {
+                Some(code) => code,
+                None => continue,
+            };
+            let key = Key::try_from_linux_keycode(code).unwrap();
+            assert_eq!(key.linux_keycode(), Some(code), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn modifier_byte() {
+        assert_eq!(Key::modifiers_from(0x00), vec![]);
+        assert_eq!(Key::modifiers_from(0x08), vec![Key::MetaLeft]);
+        assert_eq!(Key::modifiers_from(0x80), vec![Key::MetaRight]);
+        assert_eq!(Key::modifiers_from(0xFF), MODIFIER_KEYS.to_vec());
+
+        let mut keyboard = Keyboard::new();
+        let mut diff = |modifiers: u8| {
+            let mut events = Vec::new();
+            keyboard.modifier_events(modifiers, &mut events);
+            summary(events)
+        };
+
+        // Left Meta and Right Shift pressed
+        assert_eq!(diff(0x28), vec![(Key::MetaLeft, true), (Key::ShiftRight, true)]);
+        // Unchanged byte gives no events
+        assert_eq!(diff(0x28), vec![]);
+        // Right Meta pressed while Left Meta is released, presses come first
+        assert_eq!(diff(0xA0), vec![(Key::MetaRight, true), (Key::MetaLeft, false)]);
+        assert_eq!(diff(0x00), vec![(Key::ShiftRight, false), (Key::MetaRight, false)]);
+    }
+
+    #[test]
+    fn boot_report_keeps_held_keys() {
+        let mut keyboard = Keyboard::new();
+
+        // A pressed
+        let events = keyboard.events(&[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(summary(events), vec![(Key::A, true)]);
+
+        // Left Ctrl and B pressed while A is held
+        let events = keyboard.events(&[0x01, 0x00, 0x04, 0x05, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(summary(events), vec![(Key::ControlLeft, true), (Key::B, true)]);
+
+        // A released, empty slot before B must not stop the scan
+        let events = keyboard.events(&[0x01, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(summary(events), vec![(Key::A, false)]);
+
+        // Rollover keeps previous state
+        let events = keyboard.events(&[0x01, 0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01]);
+        assert!(events.is_empty());
+
+        let events = keyboard.events(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(summary(events), vec![(Key::ControlLeft, false), (Key::B, false)]);
+    }
+
+    #[test]
+    fn bitmap_report() {
+        let mut keyboard = Keyboard::new();
+
+        // Captured from NKRO keyboard: modifiers followed by 0x00-0x77 usage bitmap
+        let mut report = [0u8; 16];
+        // A (0x04) and Kp1 (0x59)
+        report[1] = 0x10;
+        report[1 + 0x59 / 8] |= 1 << (0x59 % 8);
+        let events = keyboard.bitmap_events(&report);
+        assert_eq!(summary(events), vec![(Key::A, true), (Key::Kp1, true)]);
+
+        // Right Shift pressed, A released, Kp1 still held, F13 (0x68) pressed
+        report[0] = 0x20;
+        report[1] = 0x00;
+        report[1 + 0x68 / 8] |= 1 << (0x68 % 8);
+        let events = keyboard.bitmap_events(&report);
+        assert_eq!(
+            summary(events),
+            vec![(Key::ShiftRight, true), (Key::A, false), (Key::F13, true)]
+        );
+
+        let events = keyboard.bitmap_events(&[0u8; 16]);
+        assert_eq!(
+            summary(events),
+            vec![(Key::ShiftRight, false), (Key::Kp1, false), (Key::F13, false)]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "rdev")]
+    fn native_rdev_keys() {
+        let table = [
+            (Key::Up, rdev::Key::UpArrow),
+            (Key::Down, rdev::Key::DownArrow),
+            (Key::Left, rdev::Key::LeftArrow),
+            (Key::Right, rdev::Key::RightArrow),
+            (Key::Insert, rdev::Key::Insert),
+            (Key::Home, rdev::Key::Home),
+            (Key::End, rdev::Key::End),
+            (Key::PageUp, rdev::Key::PageUp),
+            (Key::PageDown, rdev::Key::PageDown),
+            (Key::NumLock, rdev::Key::NumLock),
+            (Key::KpEnter, rdev::Key::KpReturn),
+            (Key::Kp0, rdev::Key::Kp0),
+            (Key::Kp5, rdev::Key::Kp5),
+            (Key::KpDot, rdev::Key::KpDelete),
+            (Key::MetaLeft, rdev::Key::MetaLeft),
+        ];
+
+        for (key, expected) in table {
+            assert_eq!(key.try_into_rdev(), Ok(expected), "{:?}", key);
+        }
+
+        // Every named rdev key is sent by some key, not as a raw keycode.
+        // Fn is handled by keyboards themselves, no report carries it.
+        let sent: Vec<rdev::Key> = Key::all().filter_map(|k| k.try_into_rdev().ok()).collect();
+        let named = [
+            rdev::Key::Alt,
+            rdev::Key::AltGr,
+            rdev::Key::Backspace,
+            rdev::Key::CapsLock,
+            rdev::Key::ControlLeft,
+            rdev::Key::ControlRight,
+            rdev::Key::Delete,
+            rdev::Key::DownArrow,
+            rdev::Key::End,
+            rdev::Key::Escape,
+            rdev::Key::F1,
+            rdev::Key::F10,
+            rdev::Key::F11,
+            rdev::Key::F12,
+            rdev::Key::F2,
+            rdev::Key::F3,
+            rdev::Key::F4,
+            rdev::Key::F5,
+            rdev::Key::F6,
+            rdev::Key::F7,
+            rdev::Key::F8,
+            rdev::Key::F9,
+            rdev::Key::Home,
+            rdev::Key::LeftArrow,
+            rdev::Key::MetaLeft,
+            rdev::Key::MetaRight,
+            rdev::Key::PageDown,
+            rdev::Key::PageUp,
+            rdev::Key::Return,
+            rdev::Key::RightArrow,
+            rdev::Key::ShiftLeft,
+            rdev::Key::ShiftRight,
+            rdev::Key::Space,
+            rdev::Key::Tab,
+            rdev::Key::UpArrow,
+            rdev::Key::PrintScreen,
+            rdev::Key::ScrollLock,
+            rdev::Key::Pause,
+            rdev::Key::NumLock,
+            rdev::Key::BackQuote,
+            rdev::Key::Num1,
+            rdev::Key::Num2,
+            rdev::Key::Num3,
+            rdev::Key::Num4,
+            rdev::Key::Num5,
+            rdev::Key::Num6,
+            rdev::Key::Num7,
+            rdev::Key::Num8,
+            rdev::Key::Num9,
+            rdev::Key::Num0,
+            rdev::Key::Minus,
+            rdev::Key::Equal,
+            rdev::Key::KeyQ,
+            rdev::Key::KeyW,
+            rdev::Key::KeyE,
+            rdev::Key::KeyR,
+            rdev::Key::KeyT,
+            rdev::Key::KeyY,
+            rdev::Key::KeyU,
+            rdev::Key::KeyI,
+            rdev::Key::KeyO,
+            rdev::Key::KeyP,
+            rdev::Key::LeftBracket,
+            rdev::Key::RightBracket,
+            rdev::Key::KeyA,
+            rdev::Key::KeyS,
+            rdev::Key::KeyD,
+            rdev::Key::KeyF,
+            rdev::Key::KeyG,
+            rdev::Key::KeyH,
+            rdev::Key::KeyJ,
+            rdev::Key::KeyK,
+            rdev::Key::KeyL,
+            rdev::Key::SemiColon,
+            rdev::Key::Quote,
+            rdev::Key::BackSlash,
+            rdev::Key::IntlBackslash,
+            rdev::Key::KeyZ,
+            rdev::Key::KeyX,
+            rdev::Key::KeyC,
+            rdev::Key::KeyV,
+            rdev::Key::KeyB,
+            rdev::Key::KeyN,
+            rdev::Key::KeyM,
+            rdev::Key::Comma,
+            rdev::Key::Dot,
+            rdev::Key::Slash,
+            rdev::Key::Insert,
+            rdev::Key::KpReturn,
+            rdev::Key::KpMinus,
+            rdev::Key::KpPlus,
+            rdev::Key::KpMultiply,
+            rdev::Key::KpDivide,
+            rdev::Key::Kp0,
+            rdev::Key::Kp1,
+            rdev::Key::Kp2,
+            rdev::Key::Kp3,
+            rdev::Key::Kp4,
+            rdev::Key::Kp5,
+            rdev::Key::Kp6,
+            rdev::Key::Kp7,
+            rdev::Key::Kp8,
+            rdev::Key::Kp9,
+            rdev::Key::KpDelete,
+        ];
+        for key in named {
+            assert!(sent.contains(&key), "{:?}", key);
         }
     }
 }
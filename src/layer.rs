@@ -0,0 +1,141 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::binding::{self, MacrosBinding};
+use crate::config::LayerMode;
+use crate::keyboard::Key;
+use crate::trigger::GestureType;
+
+// Index of the base layer, it is always active
+pub const BASE_LAYER: usize = 0;
+
+// Built-in action switching layers of the device
+pub struct LayerAction {
+    pub layer: usize,
+    pub mode: LayerMode,
+}
+
+// Active layers of a device. Higher layers shadow lower ones,
+// keys unbound in a layer fall through to the layers below.
+pub struct LayerStack {
+    toggled: Vec<bool>,
+    held: Vec<u32>,
+    oneshot: Option<usize>,
+    // Layer which resolved the key on its press, used until the next press
+    key_layers: HashMap<Key, usize>,
+}
+
+impl LayerStack {
+    pub fn new(count: usize) -> LayerStack {
+        LayerStack {
+            toggled: vec![false; count],
+            held: vec![0; count],
+            oneshot: None,
+            key_layers: HashMap::new(),
+        }
+    }
+
+    pub fn is_active(&self, layer: usize) -> bool {
+        layer == BASE_LAYER
+            || self.toggled[layer]
+            || self.held[layer] > 0
+            || self.oneshot == Some(layer)
+    }
+
+    // Resolves the topmost active layer binding the chord and remembers it for the key
+    pub fn press(&mut self, layers: &[MacrosBinding], key: &Key, modifiers: &BTreeSet<Key>) -> usize {
+        let layer = (0..layers.len())
+            .rev()
+            .filter(|l| self.is_active(*l))
+            .find(|l| !binding::find(&layers[*l], key, modifiers).is_empty())
+            .unwrap_or(BASE_LAYER);
+
+        // One-shot layer lasts for a single key, modifiers do not use it up
+        if !key.is_modifier() {
+            self.oneshot = None;
+        }
+
+        self.key_layers.insert(key.clone(), layer);
+        layer
+    }
+
+    pub fn layer_of(&self, key: &Key) -> usize {
+        self.key_layers.get(key).copied().unwrap_or(BASE_LAYER)
+    }
+
+    // Applies layer action for the gesture. Hold mode follows the key,
+    // other modes run on the gesture selected by the trigger.
+    pub fn apply(&mut self, action: &LayerAction, gesture: GestureType, triggered: bool) {
+        match action.mode {
+            LayerMode::Hold => match gesture {
+                GestureType::Press => self.held[action.layer] += 1,
+                GestureType::Up => {
+                    self.held[action.layer] = self.held[action.layer].saturating_sub(1)
+                }
+                _ => {}
+            },
+            LayerMode::Toggle if triggered => {
+                self.toggled[action.layer] = !self.toggled[action.layer];
+            }
+            LayerMode::OneShot if triggered => self.oneshot = Some(action.layer),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binding::{Action, Binding, Chord, Trigger};
+    use crate::config::ModifiersMatch;
+
+    fn layer(keys: &[Key]) -> MacrosBinding {
+        keys.iter()
+            .map(|key| {
+                (
+                    Chord {
+                        modifiers: BTreeSet::new(),
+                        key: key.clone(),
+                    },
+                    vec![Binding {
+                        matching: ModifiersMatch::Exact,
+                        trigger: Trigger::Release,
+                        action: Action::Layer(LayerAction {
+                            layer: BASE_LAYER,
+                            mode: LayerMode::Toggle,
+                        }),
+                    }],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn layers_fall_through_and_one_shot() {
+        let layers = vec![layer(&[Key::A, Key::B]), layer(&[Key::A])];
+        let mut stack = LayerStack::new(layers.len());
+        let none = BTreeSet::new();
+
+        assert_eq!(stack.press(&layers, &Key::A, &none), BASE_LAYER);
+
+        let hold = LayerAction {
+            layer: 1,
+            mode: LayerMode::Hold,
+        };
+        stack.apply(&hold, GestureType::Press, false);
+        assert_eq!(stack.press(&layers, &Key::A, &none), 1);
+        // B is not bound in the upper layer
+        assert_eq!(stack.press(&layers, &Key::B, &none), BASE_LAYER);
+        // Key keeps the layer it was pressed in
+        stack.apply(&hold, GestureType::Up, false);
+        assert_eq!(stack.layer_of(&Key::A), 1);
+
+        let oneshot = LayerAction {
+            layer: 1,
+            mode: LayerMode::OneShot,
+        };
+        stack.apply(&oneshot, GestureType::Release, true);
+        assert_eq!(stack.press(&layers, &Key::ShiftLeft, &none), BASE_LAYER);
+        assert_eq!(stack.press(&layers, &Key::A, &none), 1);
+        assert_eq!(stack.press(&layers, &Key::A, &none), BASE_LAYER);
+    }
+}
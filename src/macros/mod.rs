@@ -1,7 +1,47 @@
 // Basic Macro trait
+pub mod passthrough;
+pub mod sequence;
 pub mod shell;
 pub mod shortcut;
+pub mod text;
+
+use std::time::Duration;
+
+use crate::executor::CancelToken;
+use crate::keyboard::KeyEventType;
+use crate::output::{self, OutputBackend, OutputEvent};
 
 pub trait Macro: Sync {
-    fn execute(&self);
+    // Runs the macro, event type tells whether the bound key went down or up.
+    // Long running macros stop early once the token is cancelled.
+    fn execute(&self, event_type: &KeyEventType, cancel: &CancelToken);
+
+    // Passthrough macros follow the key, they get both its press and release
+    fn is_passthrough(&self) -> bool {
+        false
+    }
+}
+
+pub fn send(output: &dyn OutputBackend, event: &OutputEvent, cancel: &CancelToken) -> bool {
+    send_delayed(output, event, Duration::from_millis(20), cancel)
+}
+
+// Sends the event and waits, so the system has time to process it, returns false
+// when the run was cancelled. Keys are tracked by the token, so they can be
+// released when the run is cancelled.
+pub fn send_delayed(
+    output: &dyn OutputBackend,
+    event: &OutputEvent,
+    delay: Duration,
+    cancel: &CancelToken,
+) -> bool {
+    if let Err(e) = output::send(output, event) {
+        println!("Error during sending key: {:?} ({})", event, e);
+    }
+    match event {
+        OutputEvent::KeyDown(key) => cancel.pressed(key.clone()),
+        OutputEvent::KeyUp(key) => cancel.released(key),
+        OutputEvent::Mouse(_) => {}
+    }
+    cancel.sleep(delay)
 }
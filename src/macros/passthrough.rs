@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use super::{send, Macro};
+use crate::executor::CancelToken;
+use crate::keyboard::{Key, KeyEventType};
+use crate::output::{OutputBackend, OutputEvent};
+
+// Macro holding its keys down for as long as the bound key is held
+pub struct Passthrough {
+    keys: Vec<Key>,
+    output: Arc<dyn OutputBackend>,
+}
+
+impl Macro for Passthrough {
+    fn execute(&self, event_type: &KeyEventType, cancel: &CancelToken) {
+        match event_type {
+            KeyEventType::PRESSED => {
+                for key in self.keys.iter() {
+                    send(
+                        self.output.as_ref(),
+                        &OutputEvent::KeyDown(key.clone()),
+                        cancel,
+                    );
+                }
+            }
+            KeyEventType::RELEASED => {
+                for key in self.keys.iter().rev() {
+                    send(
+                        self.output.as_ref(),
+                        &OutputEvent::KeyUp(key.clone()),
+                        cancel,
+                    );
+                }
+            }
+        }
+    }
+
+    fn is_passthrough(&self) -> bool {
+        true
+    }
+}
+
+impl Passthrough {
+    pub fn new(keys: Vec<Key>, output: Arc<dyn OutputBackend>) -> Passthrough {
+        Passthrough { keys, output }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::recording::RecordingOutput;
+
+    #[test]
+    fn follows_bound_key_and_releases_in_reverse() {
+        let output = Arc::new(RecordingOutput::default());
+        let passthrough = Passthrough::new(vec![Key::ShiftLeft, Key::A], output.clone());
+        let cancel = CancelToken::default();
+
+        passthrough.execute(&KeyEventType::PRESSED, &cancel);
+        assert_eq!(
+            output.events(),
+            vec![
+                OutputEvent::KeyDown(Key::ShiftLeft),
+                OutputEvent::KeyDown(Key::A),
+            ]
+        );
+
+        passthrough.execute(&KeyEventType::RELEASED, &cancel);
+        assert_eq!(
+            output.events()[2..],
+            [
+                OutputEvent::KeyUp(Key::A),
+                OutputEvent::KeyUp(Key::ShiftLeft)
+            ]
+        );
+    }
+}
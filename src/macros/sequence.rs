@@ -0,0 +1,103 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{send, Macro};
+use crate::executor::CancelToken;
+use crate::keyboard::{Key, KeyEventType};
+use crate::output::{MouseEvent, OutputBackend, OutputEvent};
+
+pub enum Step {
+    Macro(Box<dyn Macro + Send>),
+    KeyDown(Key),
+    KeyUp(Key),
+    Mouse(MouseEvent),
+    Wait(Duration),
+}
+
+// Macro running its steps in order
+pub struct Sequence {
+    steps: Vec<Step>,
+    output: Arc<dyn OutputBackend>,
+}
+
+impl Macro for Sequence {
+    fn execute(&self, event_type: &KeyEventType, cancel: &CancelToken) {
+        let output = self.output.as_ref();
+        for step in self.steps.iter() {
+            if cancel.is_cancelled() {
+                return;
+            }
+            let done = match step {
+                Step::Macro(action) => {
+                    action.execute(event_type, cancel);
+                    true
+                }
+                Step::KeyDown(key) => send(output, &OutputEvent::KeyDown(key.clone()), cancel),
+                Step::KeyUp(key) => send(output, &OutputEvent::KeyUp(key.clone()), cancel),
+                Step::Mouse(event) => send(output, &OutputEvent::Mouse(event.clone()), cancel),
+                Step::Wait(duration) => cancel.sleep(*duration),
+            };
+            if !done {
+                return;
+            }
+        }
+    }
+}
+
+impl Sequence {
+    pub fn new(steps: Vec<Step>, output: Arc<dyn OutputBackend>) -> Sequence {
+        Sequence { steps, output }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macros::shortcut::ShortCut;
+    use crate::output::recording::RecordingOutput;
+    use crate::output::MouseButton;
+
+    #[test]
+    fn runs_steps_in_order() {
+        let output = Arc::new(RecordingOutput::default());
+        let sequence = Sequence::new(
+            vec![
+                Step::KeyDown(Key::ControlLeft),
+                Step::Mouse(MouseEvent::ButtonDown(MouseButton::Left)),
+                Step::Mouse(MouseEvent::ButtonUp(MouseButton::Left)),
+                Step::KeyUp(Key::ControlLeft),
+                Step::Wait(Duration::ZERO),
+                Step::Macro(Box::new(ShortCut::new(vec![Key::Enter], output.clone()))),
+                Step::Mouse(MouseEvent::Scroll { x: 0, y: -2 }),
+            ],
+            output.clone(),
+        );
+
+        sequence.execute(&KeyEventType::PRESSED, &CancelToken::default());
+
+        assert_eq!(
+            output.events(),
+            vec![
+                OutputEvent::KeyDown(Key::ControlLeft),
+                OutputEvent::Mouse(MouseEvent::ButtonDown(MouseButton::Left)),
+                OutputEvent::Mouse(MouseEvent::ButtonUp(MouseButton::Left)),
+                OutputEvent::KeyUp(Key::ControlLeft),
+                OutputEvent::KeyDown(Key::Enter),
+                OutputEvent::KeyUp(Key::Enter),
+                OutputEvent::Mouse(MouseEvent::Scroll { x: 0, y: -2 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let output = Arc::new(RecordingOutput::default());
+        let sequence = Sequence::new(vec![Step::KeyDown(Key::A)], output.clone());
+        let cancel = CancelToken::default();
+        cancel.cancel();
+
+        sequence.execute(&KeyEventType::PRESSED, &cancel);
+
+        assert!(output.events().is_empty());
+    }
+}
@@ -1,10 +1,12 @@
 use serde::Deserialize;
 
 use super::Macro;
+use crate::executor::CancelToken;
+use crate::keyboard::KeyEventType;
 use std::{collections::HashMap, process::Command};
 
 // Macro capable of running shell commands
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct ShellMacro {
     pub command: String,
     #[cfg(any(target_os = "linux"))]
@@ -15,7 +17,7 @@ pub struct ShellMacro {
 
 impl Macro for ShellMacro {
     #[cfg(any(target_os = "linux"))]
-    fn execute(&self) {
+    fn execute(&self, _event_type: &KeyEventType, _cancel: &CancelToken) {
         use std::os::unix::process::CommandExt;
 
         let mut command = Command::new(&self.command);
@@ -32,7 +34,7 @@ impl Macro for ShellMacro {
     }
 
     #[cfg(any(target_os = "windows"))]
-    fn execute(&self) {
+    fn execute(&self, _event_type: &KeyEventType, _cancel: &CancelToken) {
         let _ = Command::new(&self.command)
             .args(&self.args)
             .spawn();
@@ -1,46 +1,66 @@
-use rdev::{simulate, EventType, SimulateError};
+use std::sync::Arc;
 
-use super::Macro;
-use crate::keyboard;
+use super::{send, Macro};
+use crate::executor::CancelToken;
+use crate::keyboard::{Key, KeyEventType};
+use crate::output::{OutputBackend, OutputEvent};
 
 pub struct ShortCut {
-    keys: Vec<rdev::Key>,
+    keys: Vec<Key>,
+    output: Arc<dyn OutputBackend>,
 }
 
 impl Macro for ShortCut {
-    fn execute(&self) {
+    fn execute(&self, _event_type: &KeyEventType, cancel: &CancelToken) {
+        // Keys left held by a cancelled run are released by the runner
         for key in self.keys.iter() {
-            ShortCut::send(&EventType::KeyPress(*key));
+            if !send(
+                self.output.as_ref(),
+                &OutputEvent::KeyDown(key.clone()),
+                cancel,
+            ) {
+                return;
+            }
         }
 
         for key in self.keys.iter() {
-            ShortCut::send(&EventType::KeyRelease(*key));
+            if !send(
+                self.output.as_ref(),
+                &OutputEvent::KeyUp(key.clone()),
+                cancel,
+            ) {
+                return;
+            }
         }
     }
 }
 
 impl ShortCut {
-    pub fn new(keys: Vec<keyboard::Key>) -> ShortCut {
-        let mut parsed_keys: Vec<rdev::Key> = Vec::new();
+    pub fn new(keys: Vec<Key>, output: Arc<dyn OutputBackend>) -> ShortCut {
+        ShortCut { keys, output }
+    }
+}
 
-        for key in keys.iter() {
-            match key.try_into_rdev() {
-                Ok(k) => parsed_keys.push(k),
-                Err(_) => panic!("Unsupported key in configuration"),
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::recording::RecordingOutput;
 
-        return ShortCut { keys: parsed_keys };
-    }
+    #[test]
+    fn presses_all_keys_then_releases_them() {
+        let output = Arc::new(RecordingOutput::default());
+        let shortcut = ShortCut::new(vec![Key::ControlLeft, Key::C], output.clone());
 
-    fn send(event_type: &EventType) {
-        let delay = std::time::Duration::from_millis(20);
-        match simulate(event_type) {
-            Ok(()) => (),
-            Err(SimulateError) => {
-                println!("Error during sending key: {:?}", event_type);
-            }
-        }
-        std::thread::sleep(delay);
+        shortcut.execute(&KeyEventType::PRESSED, &CancelToken::default());
+
+        assert_eq!(
+            output.events(),
+            vec![
+                OutputEvent::KeyDown(Key::ControlLeft),
+                OutputEvent::KeyDown(Key::C),
+                OutputEvent::KeyUp(Key::ControlLeft),
+                OutputEvent::KeyUp(Key::C),
+            ]
+        );
     }
 }
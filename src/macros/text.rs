@@ -0,0 +1,115 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{send_delayed, Macro};
+use crate::executor::CancelToken;
+use crate::keyboard::KeyEventType;
+use crate::output::OutputBackend;
+
+// Macro typing text character by character, using the layout of the output
+pub struct TypeText {
+    text: String,
+    delay: Duration,
+    output: Arc<dyn OutputBackend>,
+}
+
+impl Macro for TypeText {
+    fn execute(&self, _event_type: &KeyEventType, cancel: &CancelToken) {
+        for event in self.output.text_events(&self.text) {
+            if cancel.is_cancelled()
+                || !send_delayed(self.output.as_ref(), &event, self.delay, cancel)
+            {
+                return;
+            }
+        }
+    }
+}
+
+impl TypeText {
+    pub fn new(text: String, delay: Duration, output: Arc<dyn OutputBackend>) -> TypeText {
+        TypeText {
+            text,
+            delay,
+            output,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::Key;
+    use crate::output::recording::RecordingOutput;
+    use crate::output::{MouseEvent, OutputEvent};
+    use std::sync::Mutex;
+
+    #[test]
+    fn types_shifted_and_unicode_characters() {
+        let output = Arc::new(RecordingOutput::default());
+        let text = TypeText::new("a!é".to_string(), Duration::ZERO, output.clone());
+
+        text.execute(&KeyEventType::PRESSED, &CancelToken::default());
+
+        use OutputEvent::{KeyDown as P, KeyUp as R};
+        assert_eq!(
+            output.events(),
+            vec![
+                P(Key::A),
+                R(Key::A),
+                P(Key::ShiftLeft),
+                P(Key::Num1),
+                R(Key::Num1),
+                R(Key::ShiftLeft),
+                P(Key::ControlLeft),
+                P(Key::ShiftLeft),
+                P(Key::U),
+                R(Key::U),
+                R(Key::ShiftLeft),
+                R(Key::ControlLeft),
+                P(Key::E),
+                R(Key::E),
+                P(Key::Num9),
+                R(Key::Num9),
+                P(Key::Space),
+                R(Key::Space),
+            ]
+        );
+    }
+
+    // Output cancelling the run on the first key press
+    struct Cancelling {
+        cancel: CancelToken,
+        sent: Mutex<usize>,
+    }
+
+    impl OutputBackend for Cancelling {
+        fn key_down(&self, _key: &Key) -> Result<(), String> {
+            *self.sent.lock().unwrap() += 1;
+            self.cancel.cancel();
+            Ok(())
+        }
+
+        fn key_up(&self, _key: &Key) -> Result<(), String> {
+            *self.sent.lock().unwrap() += 1;
+            Ok(())
+        }
+
+        fn mouse(&self, _event: &MouseEvent) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stops_waiting_when_cancelled() {
+        let cancel = CancelToken::default();
+        let output = Arc::new(Cancelling {
+            cancel: cancel.clone(),
+            sent: Mutex::new(0),
+        });
+        let delay = Duration::from_secs(3600);
+        let text = TypeText::new("ab".to_string(), delay, output.clone());
+
+        text.execute(&KeyEventType::PRESSED, &cancel);
+        assert_eq!(*output.sent.lock().unwrap(), 1);
+    }
+}
@@ -1,13 +1,32 @@
 mod app;
+mod binding;
+mod check;
 mod config;
+mod consumer;
+mod descriptor;
 mod device;
+mod error;
+mod executor;
+mod format;
+mod hotplug;
+mod include;
+mod input;
 mod keyboard;
+mod layer;
 mod macros;
+mod output;
+mod reconnect;
+mod report;
+mod stop;
+mod trigger;
 
-use std::fs;
+use std::path::Path;
+use std::process;
 
 use app::App;
 use clap::Parser;
+use error::Error;
+use format::Format;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -16,19 +35,44 @@ struct Args {
     list_devices: bool,
     #[structopt(short = 'c', required_unless_present = "list-devices")]
     config: Option<String>,
+    // Only validates the configuration, devices are not touched
+    #[structopt(long = "check", requires = "config")]
+    check: bool,
+    // Format of the configuration file (yaml, toml or json), its extension tells by default
+    #[structopt(long = "format", requires = "config")]
+    format: Option<Format>,
 }
 
 fn main() {
     let args = Args::parse();
 
-    if args.list_devices {
-        app::list_devices();
-        return;
+    let result = if args.list_devices {
+        app::list_devices()
+    } else {
+        let config_path = args.config.unwrap();
+        let format = args
+            .format
+            .unwrap_or_else(|| Format::of(Path::new(&config_path)));
+        if args.check {
+            check(&config_path, format)
+        } else {
+            run(&config_path, format)
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(e.exit_code());
     }
+}
 
-    let config_content = fs::read_to_string(args.config.unwrap()).unwrap();
-    let configuration: config::Config = serde_yaml::from_str(config_content.as_str()).unwrap();
+fn check(config_path: &str, format: Format) -> Result<(), Error> {
+    config::load(config_path, format)?;
+    println!("{}: configuration is valid", config_path);
+    Ok(())
+}
 
-    let mut app = App::new(configuration).unwrap();
-    app.run().unwrap();
+fn run(config_path: &str, format: Format) -> Result<(), Error> {
+    let mut app = App::new(config_path, format)?;
+    app.run()
 }
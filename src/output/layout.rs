@@ -0,0 +1,129 @@
+use super::OutputEvent;
+use crate::keyboard::Key;
+
+// US keyboard layout: key producing the character and whether Shift is needed
+pub fn us_key(c: char) -> Option<(Key, bool)> {
+    let key = match c.to_ascii_lowercase() {
+        'a' => Key::A,
+        'b' => Key::B,
+        'c' => Key::C,
+        'd' => Key::D,
+        'e' => Key::E,
+        'f' => Key::F,
+        'g' => Key::G,
+        'h' => Key::H,
+        'i' => Key::I,
+        'j' => Key::J,
+        'k' => Key::K,
+        'l' => Key::L,
+        'm' => Key::M,
+        'n' => Key::N,
+        'o' => Key::O,
+        'p' => Key::P,
+        'q' => Key::Q,
+        'r' => Key::R,
+        's' => Key::S,
+        't' => Key::T,
+        'u' => Key::U,
+        'v' => Key::V,
+        'w' => Key::W,
+        'x' => Key::X,
+        'y' => Key::Y,
+        'z' => Key::Z,
+        _ => return us_symbol(c),
+    };
+
+    Some((key, c.is_ascii_uppercase()))
+}
+
+fn us_symbol(c: char) -> Option<(Key, bool)> {
+    let mapping = match c {
+        '1' => (Key::Num1, false),
+        '2' => (Key::Num2, false),
+        '3' => (Key::Num3, false),
+        '4' => (Key::Num4, false),
+        '5' => (Key::Num5, false),
+        '6' => (Key::Num6, false),
+        '7' => (Key::Num7, false),
+        '8' => (Key::Num8, false),
+        '9' => (Key::Num9, false),
+        '0' => (Key::Num0, false),
+        '!' => (Key::Num1, true),
+        '@' => (Key::Num2, true),
+        '#' => (Key::Num3, true),
+        '$' => (Key::Num4, true),
+        '%' => (Key::Num5, true),
+        '^' => (Key::Num6, true),
+        '&' => (Key::Num7, true),
+        '*' => (Key::Num8, true),
+        '(' => (Key::Num9, true),
+        ')' => (Key::Num0, true),
+        '-' => (Key::Minus, false),
+        '_' => (Key::Minus, true),
+        '=' => (Key::Equal, false),
+        '+' => (Key::Equal, true),
+        '[' => (Key::LeftBrace, false),
+        '{' => (Key::LeftBrace, true),
+        ']' => (Key::Rightbrace, false),
+        '}' => (Key::Rightbrace, true),
+        '\\' => (Key::Backslash, false),
+        '|' => (Key::Backslash, true),
+        ';' => (Key::Semicolon, false),
+        ':' => (Key::Semicolon, true),
+        '\'' => (Key::Apostrophe, false),
+        '"' => (Key::Apostrophe, true),
+        '`' => (Key::Grave, false),
+        '~' => (Key::Grave, true),
+        ',' => (Key::Comma, false),
+        '<' => (Key::Comma, true),
+        '.' => (Key::Dot, false),
+        '>' => (Key::Dot, true),
+        '/' => (Key::Slash, false),
+        '?' => (Key::Slash, true),
+        ' ' => (Key::Space, false),
+        '\n' => (Key::Enter, false),
+        '\t' => (Key::Tab, false),
+        _ => return None,
+    };
+
+    Some(mapping)
+}
+
+// Key events typing the text on US layout.
+// Characters missing in the layout are entered as Ctrl+Shift+U unicode sequence (GTK, IBus).
+pub fn text_events(text: &str) -> Vec<OutputEvent> {
+    let mut events = Vec::new();
+
+    for c in text.chars() {
+        match us_key(c) {
+            Some((key, shift)) => tap(&mut events, key, shift),
+            None => {
+                events.push(OutputEvent::KeyDown(Key::ControlLeft));
+                events.push(OutputEvent::KeyDown(Key::ShiftLeft));
+                tap(&mut events, Key::U, false);
+                events.push(OutputEvent::KeyUp(Key::ShiftLeft));
+                events.push(OutputEvent::KeyUp(Key::ControlLeft));
+
+                for digit in format!("{:x}", c as u32).chars() {
+                    if let Some((key, shift)) = us_key(digit) {
+                        tap(&mut events, key, shift);
+                    }
+                }
+                tap(&mut events, Key::Space, false);
+            }
+        }
+    }
+
+    events
+}
+
+fn tap(events: &mut Vec<OutputEvent>, key: Key, shift: bool) {
+    if shift {
+        events.push(OutputEvent::KeyDown(Key::ShiftLeft));
+    }
+    events.push(OutputEvent::KeyDown(key.clone()));
+    events.push(OutputEvent::KeyUp(key));
+    if shift {
+        events.push(OutputEvent::KeyUp(Key::ShiftLeft));
+    }
+}
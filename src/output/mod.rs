@@ -0,0 +1,139 @@
+// Destinations of events sent by macros
+mod layout;
+#[cfg(test)]
+pub mod recording;
+#[cfg(feature = "rdev")]
+mod simulate;
+#[cfg(target_os = "linux")]
+mod uinput;
+
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::config::Output;
+use crate::error::Error;
+use crate::keyboard::Key;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    #[serde(rename = "left")]
+    Left,
+    #[serde(rename = "right")]
+    Right,
+    #[serde(rename = "middle")]
+    Middle,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MouseEvent {
+    ButtonDown(MouseButton),
+    ButtonUp(MouseButton),
+    // Wheel steps, positive y scrolls up and positive x scrolls right
+    Scroll { x: i32, y: i32 },
+    // Absolute position of the pointer on the screen
+    MoveTo { x: f64, y: f64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputEvent {
+    KeyDown(Key),
+    KeyUp(Key),
+    Mouse(MouseEvent),
+}
+
+pub trait OutputBackend: Send + Sync {
+    fn key_down(&self, key: &Key) -> Result<(), String>;
+
+    fn key_up(&self, key: &Key) -> Result<(), String>;
+
+    fn mouse(&self, event: &MouseEvent) -> Result<(), String>;
+
+    // Events typing the text, US layout by default
+    fn text_events(&self, text: &str) -> Vec<OutputEvent> {
+        layout::text_events(text)
+    }
+}
+
+pub fn send(output: &dyn OutputBackend, event: &OutputEvent) -> Result<(), String> {
+    match event {
+        OutputEvent::KeyDown(key) => output.key_down(key),
+        OutputEvent::KeyUp(key) => output.key_up(key),
+        OutputEvent::Mouse(mouse) => output.mouse(mouse),
+    }
+}
+
+// Creates backend selected in configuration
+pub fn backend(output: Output) -> Result<Arc<dyn OutputBackend>, Error> {
+    match output {
+        #[cfg(feature = "rdev")]
+        Output::Rdev => Ok(Arc::new(simulate::RdevOutput)),
+        #[cfg(not(feature = "rdev"))]
+        Output::Rdev => Err(Error::Config(
+            "rdev output is not available in this build".to_string(),
+        )),
+        #[cfg(target_os = "linux")]
+        Output::Uinput => match uinput::UinputOutput::new() {
+            Ok(output) => Ok(Arc::new(output)),
+            Err(e) => {
+                let message = format!("cannot create uinput virtual keyboard: {}", e);
+                match e.kind() {
+                    std::io::ErrorKind::PermissionDenied => Err(Error::Permission(message)),
+                    _ => Err(Error::Output(message)),
+                }
+            }
+        },
+        #[cfg(not(target_os = "linux"))]
+        Output::Uinput => Err(Error::Config(
+            "uinput output is available on Linux only".to_string(),
+        )),
+    }
+}
+
+// Tells whether the key can be sent through the output, without creating it
+pub fn check_key(output: Output, key: &Key) -> Result<(), Error> {
+    let supported = match output {
+        #[cfg(feature = "rdev")]
+        Output::Rdev => key.try_into_rdev().is_ok(),
+        // Output is not available at all, backend reports it
+        #[cfg(not(feature = "rdev"))]
+        Output::Rdev => true,
+        Output::Uinput => key.linux_keycode().is_some(),
+    };
+
+    match supported {
+        true => Ok(()),
+        false => Err(Error::UnsupportedKey {
+            key: key.clone(),
+            output: name(output),
+        }),
+    }
+}
+
+// Tells whether the text can be typed through the output. Characters missing in the
+// layout are entered as unicode sequences, which cannot enter control characters.
+pub fn check_text(output: Output, text: &str) -> Result<(), Error> {
+    for c in text.chars() {
+        let sendable = layout::text_events(&c.to_string())
+            .iter()
+            .all(|event| match event {
+                OutputEvent::KeyDown(key) | OutputEvent::KeyUp(key) => {
+                    check_key(output, key).is_ok()
+                }
+                OutputEvent::Mouse(_) => true,
+            });
+        if !sendable || (layout::us_key(c).is_none() && c.is_control()) {
+            return Err(Error::UnsupportedChar {
+                c,
+                output: name(output),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn name(output: Output) -> &'static str {
+    match output {
+        Output::Rdev => "rdev",
+        Output::Uinput => "uinput",
+    }
+}
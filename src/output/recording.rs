@@ -0,0 +1,35 @@
+// Output remembering sent events, so tests can check what macros emit
+use std::sync::Mutex;
+
+use super::{MouseEvent, OutputBackend, OutputEvent};
+use crate::keyboard::Key;
+
+#[derive(Default)]
+pub struct RecordingOutput {
+    events: Mutex<Vec<OutputEvent>>,
+}
+
+impl RecordingOutput {
+    pub fn events(&self) -> Vec<OutputEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    fn record(&self, event: OutputEvent) -> Result<(), String> {
+        self.events.lock().unwrap().push(event);
+        Ok(())
+    }
+}
+
+impl OutputBackend for RecordingOutput {
+    fn key_down(&self, key: &Key) -> Result<(), String> {
+        self.record(OutputEvent::KeyDown(key.clone()))
+    }
+
+    fn key_up(&self, key: &Key) -> Result<(), String> {
+        self.record(OutputEvent::KeyUp(key.clone()))
+    }
+
+    fn mouse(&self, event: &MouseEvent) -> Result<(), String> {
+        self.record(OutputEvent::Mouse(event.clone()))
+    }
+}
@@ -0,0 +1,46 @@
+// Events simulated through rdev, XTest on Linux (X11 sessions only)
+use rdev::{simulate, Button, EventType};
+
+use super::{MouseButton, MouseEvent, OutputBackend};
+use crate::keyboard::Key;
+
+pub struct RdevOutput;
+
+fn emit(event_type: &EventType) -> Result<(), String> {
+    simulate(event_type).map_err(|_| format!("cannot simulate {:?}", event_type))
+}
+
+fn rdev_key(key: &Key) -> Result<rdev::Key, String> {
+    key.try_into_rdev().map_err(|e| e.to_string())
+}
+
+fn rdev_button(button: MouseButton) -> Button {
+    match button {
+        MouseButton::Left => Button::Left,
+        MouseButton::Right => Button::Right,
+        MouseButton::Middle => Button::Middle,
+    }
+}
+
+impl OutputBackend for RdevOutput {
+    fn key_down(&self, key: &Key) -> Result<(), String> {
+        emit(&EventType::KeyPress(rdev_key(key)?))
+    }
+
+    fn key_up(&self, key: &Key) -> Result<(), String> {
+        emit(&EventType::KeyRelease(rdev_key(key)?))
+    }
+
+    fn mouse(&self, event: &MouseEvent) -> Result<(), String> {
+        let event_type = match event {
+            MouseEvent::ButtonDown(button) => EventType::ButtonPress(rdev_button(*button)),
+            MouseEvent::ButtonUp(button) => EventType::ButtonRelease(rdev_button(*button)),
+            MouseEvent::Scroll { x, y } => EventType::Wheel {
+                delta_x: *x as i64,
+                delta_y: *y as i64,
+            },
+            MouseEvent::MoveTo { x, y } => EventType::MouseMove { x: *x, y: *y },
+        };
+        emit(&event_type)
+    }
+}
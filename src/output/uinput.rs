@@ -0,0 +1,103 @@
+// Virtual keyboard and mouse created through /dev/uinput.
+// Unlike XTest it works on Wayland and without a display server.
+use std::io;
+use std::sync::Mutex;
+
+use evdev::{uinput::VirtualDevice, AttributeSet, InputEvent, KeyCode, RelativeAxisCode};
+
+use super::{MouseButton, MouseEvent, OutputBackend};
+use crate::error::Error;
+use crate::keyboard::Key;
+
+const DEVICE_NAME: &str = "keyboard2deck";
+
+// Values of EV_KEY events
+const KEY_RELEASED: i32 = 0;
+const KEY_PRESSED: i32 = 1;
+
+pub struct UinputOutput {
+    device: Mutex<VirtualDevice>,
+}
+
+fn button_code(button: MouseButton) -> KeyCode {
+    match button {
+        MouseButton::Left => KeyCode::BTN_LEFT,
+        MouseButton::Right => KeyCode::BTN_RIGHT,
+        MouseButton::Middle => KeyCode::BTN_MIDDLE,
+    }
+}
+
+fn keycode(key: &Key) -> Result<u16, String> {
+    key.linux_keycode().ok_or_else(|| {
+        let error = Error::UnsupportedKey {
+            key: key.clone(),
+            output: "uinput",
+        };
+        error.to_string()
+    })
+}
+
+impl UinputOutput {
+    pub fn new() -> io::Result<UinputOutput> {
+        let mut keys = AttributeSet::<KeyCode>::new();
+        for code in Key::all().filter_map(|key| key.linux_keycode()) {
+            keys.insert(KeyCode::new(code));
+        }
+        for button in [MouseButton::Left, MouseButton::Right, MouseButton::Middle] {
+            keys.insert(button_code(button));
+        }
+
+        let mut axes = AttributeSet::<RelativeAxisCode>::new();
+        axes.insert(RelativeAxisCode::REL_WHEEL);
+        axes.insert(RelativeAxisCode::REL_HWHEEL);
+
+        let device = VirtualDevice::builder()?
+            .name(DEVICE_NAME)
+            .with_keys(&keys)?
+            .with_relative_axes(&axes)?
+            .build()?;
+
+        Ok(UinputOutput {
+            device: Mutex::new(device),
+        })
+    }
+
+    fn emit(&self, events: &[InputEvent]) -> Result<(), String> {
+        self.device
+            .lock()
+            .unwrap()
+            .emit(events)
+            .map_err(|e| e.to_string())
+    }
+
+    fn key(&self, code: u16, value: i32) -> Result<(), String> {
+        self.emit(&[InputEvent::new(evdev::EventType::KEY.0, code, value)])
+    }
+}
+
+impl OutputBackend for UinputOutput {
+    fn key_down(&self, key: &Key) -> Result<(), String> {
+        self.key(keycode(key)?, KEY_PRESSED)
+    }
+
+    fn key_up(&self, key: &Key) -> Result<(), String> {
+        self.key(keycode(key)?, KEY_RELEASED)
+    }
+
+    fn mouse(&self, event: &MouseEvent) -> Result<(), String> {
+        match event {
+            MouseEvent::ButtonDown(button) => self.key(button_code(*button).0, KEY_PRESSED),
+            MouseEvent::ButtonUp(button) => self.key(button_code(*button).0, KEY_RELEASED),
+            MouseEvent::Scroll { x, y } => {
+                let relative = evdev::EventType::RELATIVE.0;
+                self.emit(&[
+                    InputEvent::new(relative, RelativeAxisCode::REL_HWHEEL.0, *x),
+                    InputEvent::new(relative, RelativeAxisCode::REL_WHEEL.0, *y),
+                ])
+            }
+            MouseEvent::MoveTo { .. } => {
+                Err("absolute pointer position is not supported by uinput output".to_string())
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+// Exponential backoff between attempts to reopen a failing device
+use std::time::Duration;
+
+use crate::config::Reconnect;
+
+pub struct Backoff {
+    policy: Reconnect,
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new(policy: Reconnect) -> Backoff {
+        Backoff {
+            policy,
+            failures: 0,
+        }
+    }
+
+    // Device works again, next failure starts with the initial delay
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    // Records a failure, None means no retries are left
+    pub fn fail(&mut self) -> Option<Duration> {
+        if self
+            .policy
+            .max_retries
+            .is_some_and(|max| self.failures >= max)
+        {
+            return None;
+        }
+
+        let delay = self
+            .policy
+            .delay()
+            .saturating_mul(2u32.saturating_pow(self.failures))
+            .min(self.policy.max_delay());
+        self.failures += 1;
+        Some(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_delay_up_to_limits() {
+        let mut backoff = Backoff::new(Reconnect {
+            max_retries: Some(4),
+            delay_ms: Some(100),
+            max_delay_ms: Some(500),
+        });
+
+        let ms = Duration::from_millis;
+        assert_eq!(backoff.fail(), Some(ms(100)));
+        assert_eq!(backoff.fail(), Some(ms(200)));
+        assert_eq!(backoff.fail(), Some(ms(400)));
+        assert_eq!(backoff.fail(), Some(ms(500)));
+        assert_eq!(backoff.fail(), None);
+
+        backoff.reset();
+        assert_eq!(backoff.fail(), Some(ms(100)));
+    }
+
+    #[test]
+    fn retries_forever_by_default() {
+        let mut backoff = Backoff::new(Reconnect::default());
+        for _ in 0..100 {
+            assert!(backoff.fail().is_some());
+        }
+        assert_eq!(backoff.fail(), Some(Duration::from_secs(30)));
+    }
+}
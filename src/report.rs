@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+
+use crate::config::{ReportFormat, ReportIds};
+use crate::consumer::ConsumerControl;
+use crate::descriptor::{FieldKind, Report, ReportLayout, PAGE_CONSUMER, PAGE_KEYBOARD};
+use crate::keyboard::{diff_keys, Key, KeyEvent, Keyboard};
+
+// Buffer size sufficient for boot protocol reports
+const BOOT_REPORT_SIZE: usize = 18;
+// Keyboard reports longer than boot report are NKRO bitmaps
+const BOOT_KEYBOARD_REPORT_LENGTH: usize = 8;
+// Buffer size sufficient for bitmap reports
+const BITMAP_REPORT_SIZE: usize = 64;
+
+// Decodes input reports into key events.
+// Layout from the report descriptor is used when available, boot protocol otherwise.
+pub struct ReportDecoder {
+    layout: Option<ReportLayout>,
+    previous_keys: HashMap<u8, Vec<Key>>,
+    report_ids: ReportIds,
+    report_format: ReportFormat,
+    keyboard: Keyboard,
+    consumer: ConsumerControl,
+}
+
+impl ReportDecoder {
+    pub fn new(
+        report_ids: ReportIds,
+        report_format: ReportFormat,
+        layout: Option<ReportLayout>,
+    ) -> ReportDecoder {
+        ReportDecoder {
+            // Explicit format overrides the descriptor
+            layout: match report_format {
+                ReportFormat::Auto => layout,
+                _ => None,
+            },
+            previous_keys: HashMap::new(),
+            report_ids,
+            report_format,
+            keyboard: Keyboard::new(),
+            consumer: ConsumerControl::new(),
+        }
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        match &self.layout {
+            Some(layout) => layout.max_report_size().max(BOOT_REPORT_SIZE),
+            None => BITMAP_REPORT_SIZE,
+        }
+    }
+
+    pub fn events(&mut self, report: &[u8]) -> Vec<KeyEvent> {
+        // Nothing was read, keys stay as they were
+        if report.is_empty() {
+            return Vec::new();
+        }
+
+        match &self.layout {
+            Some(layout) => {
+                let (id, body) = if layout.uses_report_ids() {
+                    match report.split_first() {
+                        Some((id, body)) => (*id, body),
+                        None => return Vec::new(),
+                    }
+                } else {
+                    (0, report)
+                };
+
+                let keys = match layout.report(id) {
+                    Some(format) => match pressed_keys(format, body) {
+                        Some(keys) => keys,
+                        None => return Vec::new(),
+                    },
+                    None => return Vec::new(),
+                };
+
+                let previous = self.previous_keys.entry(id).or_default();
+                diff_keys(previous, keys)
+            }
+            None => self.boot_events(report),
+        }
+    }
+
+    fn boot_events(&mut self, report: &[u8]) -> Vec<KeyEvent> {
+        // Device without report IDs sends plain keyboard reports
+        if self.report_ids.keyboard.is_none() && self.report_ids.consumer.is_none() {
+            return self.keyboard_events(report);
+        }
+
+        let (id, body) = match report.split_first() {
+            Some((id, body)) => (Some(*id), body),
+            None => return Vec::new(),
+        };
+
+        if id == self.report_ids.keyboard {
+            self.keyboard_events(body)
+        } else if id == self.report_ids.consumer {
+            self.consumer.events(body)
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn keyboard_events(&mut self, body: &[u8]) -> Vec<KeyEvent> {
+        let bitmap = match self.report_format {
+            ReportFormat::Bitmap => true,
+            ReportFormat::Boot => false,
+            ReportFormat::Auto => body.len() > BOOT_KEYBOARD_REPORT_LENGTH,
+        };
+
+        if bitmap {
+            self.keyboard.bitmap_events(body)
+        } else {
+            self.keyboard.events(body)
+        }
+    }
+}
+
+// Collects keys pressed in the report.
+// Returns None on keyboard rollover error, when the report has to be ignored.
+fn pressed_keys(format: &Report, body: &[u8]) -> Option<Vec<Key>> {
+    let mut keys = Vec::new();
+
+    for field in format.fields.iter() {
+        for n in 0..field.count {
+            let value = match field.value(body, n) {
+                Some(v) => v as i64,
+                None => break,
+            };
+
+            let usage = match field.kind {
+                FieldKind::Variable => {
+                    if value == 0 {
+                        continue;
+                    }
+                    field.usage(n)
+                }
+                FieldKind::Array => {
+                    if value < field.logical_min as i64 || value > field.logical_max as i64 {
+                        continue;
+                    }
+                    field.usage((value - field.logical_min as i64) as usize)
+                }
+            };
+
+            let usage = match usage {
+                Some(u) if u.id != 0 => u,
+                _ => continue,
+            };
+
+            let key = match usage.page {
+                PAGE_KEYBOARD => {
+                    if (0x01..=0x03).contains(&usage.id) {
+                        // ErrorRollOver, POSTFail, ErrorUndefined
+                        return None;
+                    }
+                    u8::try_from(usage.id)
+                        .ok()
+                        .and_then(|id| Key::try_key_from(id).ok())
+                }
+                PAGE_CONSUMER => Key::try_consumer_from(usage.id).ok(),
+                _ => continue,
+            };
+
+            match key {
+                Some(k) if !keys.contains(&k) => keys.push(k),
+                Some(_) => {}
+                None => println!("Unknown key with usage {:#06x}:{:#06x}", usage.page, usage.id),
+            }
+        }
+    }
+
+    Some(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor;
+    use crate::keyboard::summary;
+
+    // NKRO keyboard with report ID 6: modifiers and 0x00-0x77 usage bitmap
+    const NKRO: [u8; 41] = [
+        0x05, 0x01, 0x09, 0x06, 0xA1, 0x01, 0x85, 0x06, 0x05, 0x07, 0x19, 0xE0, 0x29, 0xE7,
+        0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x08, 0x81, 0x02, 0x05, 0x07, 0x19, 0x00,
+        0x29, 0x77, 0x15, 0x00, 0x25, 0x01, 0x95, 0x78, 0x75, 0x01, 0x81, 0x02, 0xC0,
+    ];
+
+    // Boot keyboard without report ID: modifiers, reserved byte and 6 key array
+    const BOOT: [u8; 45] = [
+        0x05, 0x01, 0x09, 0x06, 0xA1, 0x01, 0x05, 0x07, 0x19, 0xE0, 0x29, 0xE7, 0x15, 0x00,
+        0x25, 0x01, 0x75, 0x01, 0x95, 0x08, 0x81, 0x02, 0x95, 0x01, 0x75, 0x08, 0x81, 0x01,
+        0x95, 0x06, 0x75, 0x08, 0x15, 0x00, 0x25, 0x65, 0x05, 0x07, 0x19, 0x00, 0x29, 0x65,
+        0x81, 0x00, 0xC0,
+    ];
+
+    #[test]
+    fn empty_read_keeps_held_keys() {
+        let layout = descriptor::parse(&BOOT).unwrap();
+        let decoders = [
+            ReportDecoder::new(ReportIds::default(), ReportFormat::Auto, Some(layout)),
+            ReportDecoder::new(ReportIds::default(), ReportFormat::Boot, None),
+        ];
+
+        for mut decoder in decoders {
+            let events = decoder.events(&[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00]);
+            assert_eq!(summary(events), vec![(Key::A, true)]);
+
+            // Read timed out while A is held
+            assert!(decoder.events(&[]).is_empty());
+
+            let events = decoder.events(&[0x00; 8]);
+            assert_eq!(summary(events), vec![(Key::A, false)]);
+        }
+    }
+
+    #[test]
+    fn bitmap_detected_from_descriptor() {
+        let layout = descriptor::parse(&NKRO).unwrap();
+        let mut decoder = ReportDecoder::new(ReportIds::default(), ReportFormat::Auto, Some(layout));
+
+        // Captured: Left Shift + A + Z + Enter, all zero bytes in between
+        let report = [
+            0x06, 0x02, 0x10, 0x00, 0x00, 0x20, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00,
+        ];
+        let events = decoder.events(&report);
+        assert_eq!(
+            summary(events),
+            vec![
+                (Key::ShiftLeft, true),
+                (Key::A, true),
+                (Key::Z, true),
+                (Key::Enter, true)
+            ]
+        );
+
+        let mut report = [0u8; 17];
+        report[0] = 0x06;
+        report[2] = 0x10;
+        let events = decoder.events(&report);
+        assert_eq!(
+            summary(events),
+            vec![(Key::ShiftLeft, false), (Key::Z, false), (Key::Enter, false)]
+        );
+
+        // Reports with other IDs are ignored
+        assert!(decoder.events(&[0x02, 0xE9, 0x00]).is_empty());
+    }
+
+    #[test]
+    fn long_report_without_descriptor_is_bitmap() {
+        let mut decoder = ReportDecoder::new(ReportIds::default(), ReportFormat::Auto, None);
+
+        let mut report = [0u8; 16];
+        report[1 + 0x62 / 8] |= 1 << (0x62 % 8);
+        let events = decoder.events(&report);
+        assert_eq!(summary(events), vec![(Key::Kp0, true)]);
+    }
+
+    #[test]
+    fn reports_dispatched_by_id() {
+        let report_ids = ReportIds {
+            keyboard: Some(1),
+            consumer: Some(2),
+        };
+        let mut decoder = ReportDecoder::new(report_ids, ReportFormat::Auto, None);
+
+        // Keyboard report with A, then consumer report with Mute (0xE2)
+        let events = decoder.events(&[0x01, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(summary(events), vec![(Key::A, true)]);
+        let events = decoder.events(&[0x02, 0xE2, 0x00]);
+        assert_eq!(summary(events), vec![(Key::Mute, true)]);
+
+        // Each collection keeps its own keys, unknown IDs are ignored
+        assert!(decoder.events(&[0x03, 0x00]).is_empty());
+        let events = decoder.events(&[0x02, 0x00, 0x00]);
+        assert_eq!(summary(events), vec![(Key::Mute, false)]);
+        let events = decoder.events(&[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(summary(events), vec![(Key::A, false)]);
+    }
+}
@@ -0,0 +1,57 @@
+// Flag telling a worker to stop, shared between the worker and whoever stops it.
+// Workers sleeping on the flag wake up as soon as it is set.
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Default)]
+pub struct StopFlag(Arc<(Mutex<bool>, Condvar)>);
+
+impl StopFlag {
+    pub fn new() -> StopFlag {
+        StopFlag::default()
+    }
+
+    pub fn stop(&self) {
+        let (stopped, changed) = &*self.0;
+        *stopped.lock().unwrap() = true;
+        changed.notify_all();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        *self.0 .0.lock().unwrap()
+    }
+
+    // Sleeps for the duration unless stopped earlier, returns false when stopped
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let (stopped, changed) = &*self.0;
+        let mut stopped = stopped.lock().unwrap();
+
+        while !*stopped {
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+            stopped = changed.wait_timeout(stopped, deadline - now).unwrap().0;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn stop_wakes_sleeper() {
+        let stop = StopFlag::new();
+        assert!(stop.sleep(Duration::from_millis(1)));
+
+        let sleeping = stop.clone();
+        let sleeper = thread::spawn(move || sleeping.sleep(Duration::from_secs(60)));
+        stop.stop();
+        assert!(!sleeper.join().unwrap());
+        assert!(stop.is_stopped());
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::binding::{Binding, Trigger};
+use crate::keyboard::{Key, KeyEvent, KeyEventType};
+
+// Gesture recognized on a key, matched against binding triggers
+#[derive(Debug, PartialEq)]
+pub struct Gesture {
+    pub key: Key,
+    // Modifiers held when the key was pressed
+    pub modifiers: BTreeSet<Key>,
+    pub kind: GestureType,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum GestureType {
+    Press,
+    // Key went up, emitted on every release for passthrough macros
+    Up,
+    Release,
+    Hold,
+    DoubleTap,
+}
+
+impl GestureType {
+    // State of the key at the moment of the gesture
+    pub fn event_type(&self) -> KeyEventType {
+        match self {
+            GestureType::Press | GestureType::Hold => KeyEventType::PRESSED,
+            GestureType::Up | GestureType::Release | GestureType::DoubleTap => {
+                KeyEventType::RELEASED
+            }
+        }
+    }
+
+    pub fn matches(&self, trigger: &Trigger) -> bool {
+        matches!(
+            (self, trigger),
+            (GestureType::Press, Trigger::Press)
+                | (GestureType::Release, Trigger::Release)
+                | (GestureType::Hold, Trigger::Hold(_))
+                | (GestureType::DoubleTap, Trigger::DoubleTap(_))
+        )
+    }
+}
+
+struct KeyState {
+    modifiers: BTreeSet<Key>,
+    pressed_at: Instant,
+    hold: Option<Duration>,
+    double_tap: Option<Duration>,
+    down: bool,
+    hold_fired: bool,
+    // First tap released, waiting for the second one
+    released_at: Option<Instant>,
+    second_tap: bool,
+}
+
+// Timing aware state machine turning key events into gestures.
+// Release of a key is delayed only when the key has a double tap binding,
+// and it is suppressed when the hold binding already fired.
+pub struct Triggers {
+    keys: HashMap<Key, KeyState>,
+}
+
+impl Triggers {
+    pub fn new() -> Triggers {
+        Triggers {
+            keys: HashMap::new(),
+        }
+    }
+
+    // Modifiers are the ones held without the key itself,
+    // bindings are the ones resolved for the key when it is pressed.
+    pub fn event(
+        &mut self,
+        event: &KeyEvent,
+        modifiers: &BTreeSet<Key>,
+        bindings: &[&Binding],
+        now: Instant,
+    ) -> Vec<Gesture> {
+        let mut gestures = Vec::new();
+
+        match event.event_type {
+            KeyEventType::PRESSED => {
+                let waiting = self.keys.get_mut(&event.key).filter(|s| {
+                    s.released_at
+                        .zip(s.double_tap)
+                        .is_some_and(|(at, window)| now - at < window)
+                });
+
+                match waiting {
+                    Some(state) => {
+                        state.second_tap = true;
+                        state.released_at = None;
+                        state.down = true;
+                        state.pressed_at = now;
+                    }
+                    None => {
+                        // Pending single tap of the previous press expired
+                        gestures.extend(self.expired_taps(now));
+
+                        let (hold, double_tap) = thresholds(bindings);
+                        self.keys.insert(
+                            event.key.clone(),
+                            KeyState {
+                                modifiers: modifiers.clone(),
+                                pressed_at: now,
+                                hold,
+                                double_tap,
+                                down: true,
+                                hold_fired: false,
+                                released_at: None,
+                                second_tap: false,
+                            },
+                        );
+                    }
+                }
+
+                gestures.push(Gesture {
+                    key: event.key.clone(),
+                    modifiers: modifiers.clone(),
+                    kind: GestureType::Press,
+                });
+            }
+            KeyEventType::RELEASED => {
+                let mut state = match self.keys.remove(&event.key) {
+                    Some(s) => s,
+                    None => return gestures,
+                };
+
+                gestures.push(Gesture {
+                    key: event.key.clone(),
+                    modifiers: state.modifiers.clone(),
+                    kind: GestureType::Up,
+                });
+
+                let kind = if state.hold_fired {
+                    None
+                } else if state.second_tap {
+                    Some(GestureType::DoubleTap)
+                } else if state.double_tap.is_some() {
+                    state.down = false;
+                    state.released_at = Some(now);
+                    self.keys.insert(event.key.clone(), state);
+                    return gestures;
+                } else {
+                    Some(GestureType::Release)
+                };
+
+                if let Some(kind) = kind {
+                    gestures.push(Gesture {
+                        key: event.key.clone(),
+                        modifiers: state.modifiers,
+                        kind,
+                    });
+                }
+            }
+        }
+
+        gestures
+    }
+
+    // Recognizes gestures which depend only on time passing
+    pub fn tick(&mut self, now: Instant) -> Vec<Gesture> {
+        let mut gestures = self.expired_taps(now);
+
+        for (key, state) in self.keys.iter_mut() {
+            if !state.down || state.hold_fired || state.second_tap {
+                continue;
+            }
+
+            if let Some(threshold) = state.hold {
+                if now - state.pressed_at >= threshold {
+                    state.hold_fired = true;
+                    gestures.push(Gesture {
+                        key: key.clone(),
+                        modifiers: state.modifiers.clone(),
+                        kind: GestureType::Hold,
+                    });
+                }
+            }
+        }
+
+        gestures
+    }
+
+    // Time until the next gesture can be recognized by tick
+    pub fn next_deadline(&self, now: Instant) -> Option<Duration> {
+        self.keys
+            .values()
+            .filter_map(|state| {
+                if let (Some(at), Some(window)) = (state.released_at, state.double_tap) {
+                    return Some((at + window).saturating_duration_since(now));
+                }
+                match state.hold {
+                    Some(threshold) if state.down && !state.hold_fired && !state.second_tap => {
+                        Some((state.pressed_at + threshold).saturating_duration_since(now))
+                    }
+                    _ => None,
+                }
+            })
+            .min()
+    }
+
+    // Single taps whose double tap window passed
+    fn expired_taps(&mut self, now: Instant) -> Vec<Gesture> {
+        let expired: Vec<Key> = self
+            .keys
+            .iter()
+            .filter(|(_, s)| {
+                s.released_at
+                    .zip(s.double_tap)
+                    .is_some_and(|(at, window)| now - at >= window)
+            })
+            .map(|(k, _)| k.clone())
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|key| self.keys.remove_entry(&key))
+            .map(|(key, state)| Gesture {
+                key,
+                modifiers: state.modifiers,
+                kind: GestureType::Release,
+            })
+            .collect()
+    }
+}
+
+// Hold threshold and double tap window of the bindings
+fn thresholds(bindings: &[&Binding]) -> (Option<Duration>, Option<Duration>) {
+    let mut hold = None;
+    let mut double_tap = None;
+
+    for binding in bindings {
+        match binding.trigger {
+            Trigger::Hold(threshold) => hold = Some(threshold),
+            Trigger::DoubleTap(window) => double_tap = Some(window),
+            _ => {}
+        }
+    }
+
+    (hold, double_tap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binding::{self, Action, Chord, MacrosBinding};
+    use crate::config::ModifiersMatch;
+    use crate::config::Overlap;
+    use crate::executor::{CancelToken, Executor, MacroRunner};
+    use crate::macros::Macro;
+    use crate::output::recording::RecordingOutput;
+    use std::sync::Arc;
+
+    struct Noop;
+
+    impl Macro for Noop {
+        fn execute(&self, _event_type: &KeyEventType, _cancel: &CancelToken) {}
+    }
+
+    fn bindings(triggers: &[Trigger]) -> MacrosBinding {
+        let mut bindings = MacrosBinding::new();
+        bindings.insert(
+            Chord {
+                modifiers: BTreeSet::new(),
+                key: Key::A,
+            },
+            triggers
+                .iter()
+                .map(|t| Binding {
+                    matching: ModifiersMatch::Exact,
+                    trigger: *t,
+                    action: Action::Macro(MacroRunner::new(
+                        Box::new(Noop),
+                        Overlap::Queue,
+                        &Executor::new(Arc::new(RecordingOutput::default())),
+                    )),
+                })
+                .collect(),
+        );
+        bindings
+    }
+
+    fn event(event_type: KeyEventType) -> KeyEvent {
+        KeyEvent {
+            key: Key::A,
+            event_type,
+        }
+    }
+
+    fn kinds(gestures: Vec<Gesture>) -> Vec<GestureType> {
+        gestures.into_iter().map(|g| g.kind).collect()
+    }
+
+    #[test]
+    fn tap_and_hold() {
+        let ms = Duration::from_millis;
+        let map = bindings(&[Trigger::Release, Trigger::Hold(ms(500))]);
+        let mut triggers = Triggers::new();
+        let held = BTreeSet::new();
+        let bindings = binding::find(&map, &Key::A, &held);
+        let start = Instant::now();
+
+        // Short tap
+        let g = triggers.event(&event(KeyEventType::PRESSED), &held, &bindings, start);
+        assert_eq!(kinds(g), vec![GestureType::Press]);
+        assert_eq!(triggers.next_deadline(start), Some(ms(500)));
+        let g = triggers.event(&event(KeyEventType::RELEASED), &held, &bindings, start + ms(100));
+        assert_eq!(kinds(g), vec![GestureType::Up, GestureType::Release]);
+
+        // Long press suppresses release
+        triggers.event(&event(KeyEventType::PRESSED), &held, &bindings, start + ms(1000));
+        assert!(triggers.tick(start + ms(1400)).is_empty());
+        assert_eq!(kinds(triggers.tick(start + ms(1500))), vec![GestureType::Hold]);
+        assert!(triggers.tick(start + ms(1600)).is_empty());
+        let g = triggers.event(&event(KeyEventType::RELEASED), &held, &bindings, start + ms(2000));
+        assert_eq!(kinds(g), vec![GestureType::Up]);
+        assert_eq!(triggers.next_deadline(start + ms(2000)), None);
+    }
+
+    #[test]
+    fn single_and_double_tap() {
+        let ms = Duration::from_millis;
+        let map = bindings(&[Trigger::Release, Trigger::DoubleTap(ms(300))]);
+        let mut triggers = Triggers::new();
+        let held = BTreeSet::new();
+        let bindings = binding::find(&map, &Key::A, &held);
+        let start = Instant::now();
+
+        // Release waits for the window
+        triggers.event(&event(KeyEventType::PRESSED), &held, &bindings, start);
+        let g = triggers.event(&event(KeyEventType::RELEASED), &held, &bindings, start + ms(50));
+        assert_eq!(kinds(g), vec![GestureType::Up]);
+        assert_eq!(triggers.next_deadline(start + ms(50)), Some(ms(300)));
+        assert_eq!(kinds(triggers.tick(start + ms(350))), vec![GestureType::Release]);
+
+        // Second press within the window
+        triggers.event(&event(KeyEventType::PRESSED), &held, &bindings, start + ms(1000));
+        triggers.event(&event(KeyEventType::RELEASED), &held, &bindings, start + ms(1050));
+        let g = triggers.event(&event(KeyEventType::PRESSED), &held, &bindings, start + ms(1200));
+        assert_eq!(kinds(g), vec![GestureType::Press]);
+        let g = triggers.event(&event(KeyEventType::RELEASED), &held, &bindings, start + ms(1250));
+        assert_eq!(kinds(g), vec![GestureType::Up, GestureType::DoubleTap]);
+        assert!(triggers.tick(start + ms(2000)).is_empty());
+    }
+}